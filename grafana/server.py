@@ -6,6 +6,7 @@ from jsonpath_ng.ext import parse
 app = Flask(__name__)
 
 LOG_FILE = os.getcwd() + "/.." + "/memory_management_for_chrome/" + "output.json"
+HISTORY_DIR = os.getcwd() + "/.." + "/memory_management_for_chrome/" + "history"
 
 def read_log_file():
     # print("Grafana: {}\n", LOG_FILE)
@@ -32,5 +33,60 @@ def handle_request():
     log_data = read_log_file()
     return log_data
 
+@app.route("/status", methods=["GET"])
+def handle_status():
+    # reclamation state written by MemoryManager: failure pause, kill rate, circuit breaker
+    log_data = read_log_file()
+    if "error" in log_data:
+        return log_data
+    return jsonify(log_data.get("status", {}))
+
+def read_history(tab_id=None, url=None, time_from=None, time_to=None):
+    # segment files are written by HistoryManager, one JSON record per line
+    if not os.path.isdir(HISTORY_DIR):
+        return []
+    records = []
+    for name in sorted(os.listdir(HISTORY_DIR)):
+        if not name.startswith("segment-"):
+            continue
+        with open(os.path.join(HISTORY_DIR, name), "r", encoding="utf-8") as file:
+            for line in file:
+                # a half written or malformed line is skipped, like the manager does
+                try:
+                    record = json.loads(line)
+                except json.JSONDecodeError:
+                    continue
+                if not isinstance(record, dict) or not isinstance(record.get("url"), str):
+                    continue
+                if not isinstance(record.get("tab_id"), int) or not isinstance(record.get("timestamp"), int):
+                    continue
+                if tab_id is not None and record["tab_id"] != tab_id:
+                    continue
+                if url is not None and url not in record["url"]:
+                    continue
+                if time_from is not None and record["timestamp"] < time_from:
+                    continue
+                if time_to is not None and record["timestamp"] > time_to:
+                    continue
+                records.append(record)
+    return records
+
+@app.route("/history", methods=["GET"])
+def handle_history():
+    # /history?tab=12&url=atlassian&from=1700000000&to=1700003600
+    # get(type=int) returns None on a malformed value, which would silently drop the filter
+    args = {}
+    for name in ("tab", "from", "to"):
+        args[name] = request.args.get(name, type=int)
+        if name in request.args and args[name] is None:
+            abort(400)
+    tab_id, time_from, time_to = args["tab"], args["from"], args["to"]
+    return jsonify(read_history(tab_id, request.args.get("url"), time_from, time_to))
+
 if __name__ == "__main__":
-    app.run(debug=True, host="0.0.0.0", port=5000)
+    listen_fd = os.environ.get("LISTEN_FD")
+    if listen_fd:
+        # socket activation: serve the socket bound by systemd, the reloader cannot reuse it
+        app.run(debug=True, use_reloader=False, host="0.0.0.0", port=5000, fd=int(listen_fd))
+    else:
+        app.run(debug=True, host="0.0.0.0", port=5000)
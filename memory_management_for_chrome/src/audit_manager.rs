@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config_manager::AuditConfig;
+use crate::escalation_manager::Tier;
+use crate::history_manager::{unix_now, HistoryFilter};
+use crate::tab_manager::TabProcessInfo;
+
+/// One reclaim decision, written as a JSON line into the audit log.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditRecord {
+    pub timestamp: u64, // unix second
+    pub tab_id: i32,
+    pub title: String,
+    pub url: String,
+    pub pid: i32,
+    pub rss_before: i32, // KB
+    pub strategy: String,
+    pub reason: String,
+    pub action: String, // tier entered by the tab
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(tab: &TabProcessInfo, strategy: &str, reason: &str, tier: Tier, result: &nix::Result<()>) -> Self {
+        Self {
+            timestamp: unix_now(),
+            tab_id: tab.tab_inner_id,
+            title: tab.tab_name.clone(),
+            url: tab.url.clone(),
+            pid: tab.tab_process_id,
+            rss_before: tab.tab_rss,
+            strategy: strategy.to_string(),
+            reason: reason.to_string(),
+            action: tier.to_string(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        }
+    }
+}
+
+/// Audit log in JSON lines format at `path`. Once the file grows over `max_bytes` it is
+/// rotated to `path.1`, `path.1` to `path.2` and so on, keeping at most `max_files` old files.
+pub struct AuditManager {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub max_files: u32,
+}
+
+impl AuditManager {
+    pub fn new(config: &AuditConfig) -> Self {
+        Self { path: PathBuf::from(&config.path), max_bytes: config.max_bytes, max_files: config.max_files }
+    }
+
+    pub fn append(&self, records: &[AuditRecord]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= self.max_bytes) {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        for record in records.iter() {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the records matching `filter`, oldest first, across the rotated files.
+    pub fn query(&self, filter: &HistoryFilter) -> io::Result<Vec<AuditRecord>> {
+        let mut records = Vec::new();
+        for index in (0..=self.max_files).rev() {
+            let file = match File::open(self.rotated_path(index)) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for line in BufReader::new(file).lines() {
+                let Ok(record) = serde_json::from_str::<AuditRecord>(&line?) else {
+                    continue;
+                };
+                if filter.tab_id.is_some_and(|tab_id| record.tab_id != tab_id)
+                    || filter.url.as_ref().is_some_and(|url| !record.url.contains(url.as_str()))
+                    || filter.strategy.as_ref().is_some_and(|strategy| record.strategy != *strategy)
+                    || filter.from.is_some_and(|from| record.timestamp < from)
+                    || filter.to.is_some_and(|to| record.timestamp > to)
+                {
+                    continue;
+                }
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (0..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if Path::new(&from).exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        if index == 0 {
+            return self.path.clone();
+        }
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tab_inner_id: i32, strategy: &str) -> AuditRecord {
+        let tab = TabProcessInfo { tab_inner_id, tab_process_id: 1000 + tab_inner_id, ..Default::default() };
+        AuditRecord::new(&tab, strategy, "test", Tier::Kill, &Err(nix::errno::Errno::EPERM))
+    }
+
+    /// Audit manager writing into a fresh directory of its own.
+    fn audit_manager(name: &str, max_bytes: u64, max_files: u32) -> AuditManager {
+        let dir = std::env::temp_dir().join(format!("audit-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl").to_string_lossy().into_owned();
+        AuditManager::new(&AuditConfig { path, max_bytes, max_files })
+    }
+
+    #[test]
+    fn rotation_keeps_max_files() {
+        let audit_manager = audit_manager("rotation", 1, 2);
+        for tab_inner_id in 1..=5 {
+            audit_manager.append(&[record(tab_inner_id, "rss_limit")]).unwrap();
+        }
+        assert!(audit_manager.rotated_path(2).exists());
+        assert!(!audit_manager.rotated_path(3).exists());
+        // the two oldest records were rotated out, the rest comes back oldest first
+        let tabs: Vec<i32> = audit_manager.query(&HistoryFilter::default()).unwrap().iter().map(|record| record.tab_id).collect();
+        assert_eq!(tabs, vec![3, 4, 5]);
+        let _ = fs::remove_dir_all(audit_manager.path.parent().unwrap());
+    }
+
+    #[test]
+    fn no_rotation_below_max_bytes() {
+        let audit_manager = audit_manager("append", 1024 * 1024, 2);
+        audit_manager.append(&[record(1, "rss_limit"), record(2, "leak")]).unwrap();
+        audit_manager.append(&[]).unwrap();
+        audit_manager.append(&[record(3, "rss_limit")]).unwrap();
+        assert!(!audit_manager.rotated_path(1).exists());
+
+        let filter = HistoryFilter { strategy: Some("rss_limit".to_string()), ..Default::default() };
+        let records = audit_manager.query(&filter).unwrap();
+        assert_eq!(records.iter().map(|record| record.tab_id).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(!records[0].success);
+        assert_eq!(records[0].error.as_deref(), Some("EPERM: Operation not permitted"));
+        let _ = fs::remove_dir_all(audit_manager.path.parent().unwrap());
+    }
+}
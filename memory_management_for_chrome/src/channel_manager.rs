@@ -0,0 +1,515 @@
+use json::JsonValue;
+use log::{debug, info, warn};
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::{Error, Message, WebSocket};
+
+use crate::config_manager::ChannelConfig;
+
+const CHANNEL_PATH: &str = "/channel";
+pub const HOST_NAME: &str = "chrome_memory_manager"; // native messaging host, as passed to chrome.runtime.connectNative
+const MAX_NATIVE_MESSAGE: usize = 64 * 1024 * 1024; // Chrome never sends more to a host
+const POLL_INTERVAL: Duration = Duration::from_millis(100); // between two looks at the pending commands
+
+/// Generation and command queue of the connection in use, None while no extension is connected.
+type Connection = Arc<Mutex<Option<(u64, Sender<String>)>>>;
+
+/// Answers of the extension to discard commands since the last tick, Chrome tab id -> outcome.
+type Acks = Arc<Mutex<HashMap<i32, Result<(), String>>>>;
+
+/// Sends commands to the connected extension, cloned into whatever needs to reach the browser.
+#[derive(Clone, Default)]
+pub struct ChannelSender {
+    connection: Connection,
+    acks: Acks,
+}
+
+impl ChannelSender {
+    /// Queues a command for the extension, false when no extension is connected.
+    pub fn send(&self, command: serde_json::Value) -> bool {
+        let connection = self.connection.lock().unwrap();
+        connection.as_ref().is_some_and(|(_, commands)| commands.send(command.to_string()).is_ok())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.lock().unwrap().is_some()
+    }
+
+    /// The extension answers with a `discarded` event, collected by `take_discard_acks`.
+    pub fn discard(&self, tab_id: i32) -> bool {
+        self.send(json!({ "command": "discard", "tabId": tab_id }))
+    }
+
+    /// Answers to the discard commands that arrived since the previous call.
+    pub fn take_discard_acks(&self) -> HashMap<i32, Result<(), String>> {
+        std::mem::take(&mut *self.acks.lock().unwrap())
+    }
+
+    pub fn reload(&self, tab_id: i32) -> bool {
+        self.send(json!({ "command": "reload", "tabId": tab_id }))
+    }
+
+    /// Asks Chrome not to discard the tab on its own while the manager protects it.
+    pub fn protect(&self, tab_id: i32, protected: bool) -> bool {
+        self.send(json!({ "command": "protect", "tabId": tab_id, "protected": protected }))
+    }
+
+    pub fn notify(&self, title: &str, message: &str) -> bool {
+        self.send(json!({ "command": "notify", "title": title, "message": message }))
+    }
+
+    /// Keeps the outcome of a `discarded` event until the next tick collects it.
+    fn acknowledge(&self, event: &JsonValue) {
+        let tab_id = event["tabId"].as_i32().unwrap_or(-1);
+        let result = match event["ok"].as_bool() {
+            Some(true) => Ok(()),
+            _ => Err(event["error"].as_str().unwrap_or("the tab was not discarded").to_string()),
+        };
+        self.acks.lock().unwrap().insert(tab_id, result);
+    }
+}
+
+/// Persistent channel between the extension and the manager, either a WebSocket at
+/// ws://<address>/channel or the stdin and stdout of a native messaging host.
+///
+/// The extension sends a full snapshot when it connects, then one event per change (tab created,
+/// updated, activated or removed, page activity, windows, groups and idle state). Events are
+/// applied to `TabManager` at the start of every tick. The manager sends commands back through
+/// `ChannelSender`, the extension confirms a discard with a `discarded` event. Only the extension set in `extension_id` may connect, and only one connection
+/// at a time: another one is refused while the current one is open. A native messaging host is
+/// started by Chrome for the extension and serves it until the extension disconnects.
+pub struct ChannelManager {
+    pub events: Receiver<JsonValue>,
+    pub sender: ChannelSender,
+    pub synced: Arc<AtomicBool>, // a snapshot arrived on the current connection
+}
+
+impl ChannelManager {
+    /// Listens on `listen_fd` when systemd passes the ingestion socket, on the configured address otherwise.
+    /// Refuses to start without `extension_id`, any local page could pose as the extension otherwise.
+    pub fn start(config: &ChannelConfig, listen_fd: Option<RawFd>) -> io::Result<Self> {
+        if config.extension_id.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "[channel] extension_id is not set"));
+        }
+        let listener = match listen_fd {
+            // SAFETY: the descriptor is a listening TCP socket systemd handed to this process only
+            Some(fd) => unsafe { TcpListener::from_raw_fd(fd) },
+            None => TcpListener::bind(&config.address)?,
+        };
+        let address = listener.local_addr()?;
+        let (event_sender, events) = mpsc::channel();
+        let sender = ChannelSender::default();
+        let synced = Arc::new(AtomicBool::new(false));
+        let generation = Arc::new(AtomicU64::new(0));
+
+        let accept_sender = sender.clone();
+        let accept_synced = synced.clone();
+        let extension_id = config.extension_id.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!("Channel connection failed: {}", err);
+                        continue;
+                    }
+                };
+                let sender = accept_sender.clone();
+                let synced = accept_synced.clone();
+                let events = event_sender.clone();
+                let extension_id = extension_id.clone();
+                let id = generation.fetch_add(1, Ordering::SeqCst) + 1;
+                thread::spawn(move || handle_connection(stream, id, &extension_id, sender, synced, events));
+            }
+        });
+        info!("Extension channel listening on ws://{}{}", address, CHANNEL_PATH);
+        Ok(Self { events, sender, synced })
+    }
+
+    /// Chrome native messaging on stdin and stdout: each message is its length as a 32 bit integer
+    /// in native byte order followed by that much JSON. Chrome closes stdin when the extension
+    /// disconnects, which raises `stop_signal` so the manager exits as Chrome expects.
+    pub fn start_native(stop_signal: Arc<Mutex<bool>>) -> io::Result<Self> {
+        let (input, mut output) = take_stdio()?;
+        let (event_sender, events) = mpsc::channel();
+        let (command_sender, commands) = mpsc::channel::<String>();
+        let sender = ChannelSender::default();
+        *sender.connection.lock().unwrap() = Some((1, command_sender));
+        let synced = Arc::new(AtomicBool::new(false));
+
+        let reader_sender = sender.clone();
+        let reader_synced = synced.clone();
+        thread::spawn(move || {
+            let reason = read_native_messages(input, &reader_sender, &reader_synced, &event_sender);
+            *reader_sender.connection.lock().unwrap() = None;
+            reader_synced.store(false, Ordering::SeqCst);
+            info!("Extension disconnected from the native host: {}", reason);
+            *stop_signal.lock().unwrap() = true;
+        });
+        thread::spawn(move || {
+            for command in commands {
+                debug!("Channel command: {}", command);
+                if let Err(err) = write_native_message(&mut output, &command) {
+                    warn!("Failed to send a command to the extension: {}", err);
+                    return;
+                }
+            }
+        });
+        // the extension starts streaming once the host greets it
+        sender.send(json!({ "command": "hello" }));
+        info!("Serving the extension as native messaging host {}", HOST_NAME);
+        Ok(Self { events, sender, synced })
+    }
+}
+
+/// Gives the protocol its own copies of stdin and stdout. Descriptors 0 and 1 then point to
+/// /dev/null and stderr, so nothing else printing, such as the servers started as children,
+/// can corrupt the stream of messages.
+fn take_stdio() -> io::Result<(File, File)> {
+    let null = File::open("/dev/null")?;
+    // SAFETY: duplicates the standard descriptors of this process, the copies are owned by the returned files
+    unsafe {
+        let input = libc::fcntl(0, libc::F_DUPFD_CLOEXEC, 3);
+        if input < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let input = File::from_raw_fd(input);
+        let output = libc::fcntl(1, libc::F_DUPFD_CLOEXEC, 3);
+        if output < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let output = File::from_raw_fd(output);
+        if libc::dup2(null.as_raw_fd(), 0) < 0 || libc::dup2(2, 1) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((input, output))
+    }
+}
+
+/// Writes one native messaging frame, the length in native byte order then the JSON.
+fn write_native_message(output: &mut impl Write, message: &str) -> io::Result<()> {
+    output.write_all(&(message.len() as u32).to_ne_bytes())?;
+    output.write_all(message.as_bytes())?;
+    output.flush()
+}
+
+/// Forwards the messages of the native messaging host until stdin closes.
+fn read_native_messages(mut input: impl Read, sender: &ChannelSender, synced: &AtomicBool, events: &Sender<JsonValue>) -> String {
+    loop {
+        let mut length = [0u8; 4];
+        match input.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return "stdin closed".to_string(),
+            Err(err) => return err.to_string(),
+        }
+        let length = u32::from_ne_bytes(length) as usize;
+        if length > MAX_NATIVE_MESSAGE {
+            return format!("message of {} bytes is too large", length);
+        }
+        let mut message = vec![0; length];
+        if let Err(err) = input.read_exact(&mut message) {
+            return err.to_string();
+        }
+        match std::str::from_utf8(&message) {
+            Ok(text) => {
+                if !forward_event(text, sender, synced, events) {
+                    return "manager is shutting down".to_string();
+                }
+            }
+            Err(err) => warn!("Invalid channel event: {}", err),
+        }
+    }
+}
+
+/// Parses an event and hands it to the main loop, false once the main loop is gone. Discard
+/// confirmations are kept aside for `take_discard_acks` instead.
+fn forward_event(text: &str, sender: &ChannelSender, synced: &AtomicBool, events: &Sender<JsonValue>) -> bool {
+    match json::parse(text) {
+        Ok(event) => {
+            if event["type"] == "discarded" {
+                sender.acknowledge(&event);
+                return true;
+            }
+            if event["type"] == "snapshot" {
+                synced.store(true, Ordering::SeqCst);
+            }
+            events.send(event).is_ok()
+        }
+        Err(err) => {
+            warn!("Invalid channel event: {}", err);
+            true
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    id: u64,
+    extension_id: &str,
+    sender: ChannelSender,
+    synced: Arc<AtomicBool>,
+    events: Sender<JsonValue>,
+) {
+    // the error type is the one tungstenite expects from a handshake callback
+    #[allow(clippy::result_large_err)]
+    let check_request = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        let origin = request.headers().get("origin").and_then(|origin| origin.to_str().ok()).unwrap_or("");
+        let allowed = origin.strip_prefix("chrome-extension://").is_some_and(|origin_id| origin_id.trim_end_matches('/') == extension_id);
+        if request.uri().path() != CHANNEL_PATH || !allowed {
+            warn!("Refusing channel connection to {} from origin {:?}", request.uri().path(), origin);
+            return Err(refusal(StatusCode::FORBIDDEN, "forbidden"));
+        }
+        if sender.is_connected() {
+            warn!("Refusing channel connection, an extension is already connected");
+            return Err(refusal(StatusCode::CONFLICT, "already connected"));
+        }
+        Ok(response)
+    };
+    let mut socket = match tungstenite::accept_hdr(stream, check_request) {
+        Ok(socket) => socket,
+        Err(err) => {
+            debug!("Channel handshake failed: {}", err);
+            return;
+        }
+    };
+    if let Err(err) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        warn!("Channel connection failed: {}", err);
+        return;
+    }
+
+    let (command_sender, commands) = mpsc::channel();
+    {
+        // another connection may have completed its handshake in the meantime
+        let mut current = sender.connection.lock().unwrap();
+        if current.is_some() {
+            info!("Closing channel connection, an extension is already connected");
+            let _ = socket.close(None);
+            let _ = socket.flush();
+            return;
+        }
+        *current = Some((id, command_sender));
+    }
+    synced.store(false, Ordering::SeqCst);
+    info!("Extension connected to the channel");
+
+    let reason = serve(&mut socket, &sender, &synced, &events, &commands);
+
+    let mut current = sender.connection.lock().unwrap();
+    if current.as_ref().is_some_and(|(current_id, _)| *current_id == id) {
+        *current = None;
+        synced.store(false, Ordering::SeqCst);
+    }
+    info!("Extension channel closed: {}", reason);
+}
+
+/// Forwards events and sends queued commands until the connection closes.
+fn serve(
+    socket: &mut WebSocket<TcpStream>,
+    sender: &ChannelSender,
+    synced: &AtomicBool,
+    events: &Sender<JsonValue>,
+    commands: &Receiver<String>,
+) -> String {
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if !forward_event(&text, sender, synced, events) {
+                    return "manager is shutting down".to_string();
+                }
+            }
+            Ok(Message::Close(_)) => return "closed by the extension".to_string(),
+            Ok(_) => {}
+            Err(Error::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(err) => return err.to_string(),
+        }
+        for command in commands.try_iter() {
+            debug!("Channel command: {}", command);
+            if let Err(err) = socket.send(Message::Text(command)) {
+                return err.to_string();
+            }
+        }
+    }
+}
+
+/// Handshake response refusing a channel connection.
+fn refusal(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut refusal = ErrorResponse::new(Some(reason.to_string()));
+    *refusal.status_mut() = status;
+    refusal
+}
+
+/// Writes the native messaging host manifest and the launcher it points to, which starts the
+/// manager from the current directory so it finds manager.toml. Returns the written paths.
+/// With `install` false both files are printed instead.
+pub fn generate_host_manifest(install: bool, extension_id: &str) -> io::Result<Vec<PathBuf>> {
+    let executable = env::current_exe()?;
+    let working_directory = env::current_dir()?;
+    let home = env::var_os("HOME").ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    let host_directory = Path::new(&home).join(".config/google-chrome/NativeMessagingHosts");
+    let launcher_path = host_directory.join(format!("{}.sh", HOST_NAME));
+
+    let launcher = format!(
+        "#!/bin/sh
+cd '{}' && exec '{}' \"$@\"
+",
+        working_directory.display(),
+        executable.display(),
+    );
+    let manifest = serde_json::to_string_pretty(&json!({
+        "name": HOST_NAME,
+        "description": "Google Chrome Memory Manager",
+        "path": launcher_path,
+        "type": "stdio",
+        "allowed_origins": [format!("chrome-extension://{}/", extension_id)],
+    }))?;
+    let manifest_path = host_directory.join(format!("{}.json", HOST_NAME));
+
+    if !install {
+        println!("# {}\n{}", launcher_path.display(), launcher);
+        println!("# {}\n{}", manifest_path.display(), manifest);
+        return Ok(Vec::new());
+    }
+
+    fs::create_dir_all(&host_directory)?;
+    fs::write(&launcher_path, launcher)?;
+    fs::set_permissions(&launcher_path, fs::Permissions::from_mode(0o755))?;
+    fs::write(&manifest_path, manifest)?;
+    Ok(vec![launcher_path, manifest_path])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::client::IntoClientRequest;
+
+    const EXTENSION_ID: &str = "abcdefghijklmnopabcdefghijklmnop";
+
+    fn config(extension_id: &str) -> ChannelConfig {
+        ChannelConfig { enabled: true, address: "127.0.0.1:0".to_string(), extension_id: extension_id.to_string() }
+    }
+
+    /// None when the manager refuses the connection.
+    fn connect(address: &str, extension_id: &str) -> Option<WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>> {
+        let mut request = format!("ws://{}{}", address, CHANNEL_PATH).into_client_request().unwrap();
+        request.headers_mut().insert("origin", format!("chrome-extension://{}", extension_id).parse().unwrap());
+        tungstenite::connect(request).ok().map(|(socket, _)| socket)
+    }
+
+    fn start() -> (ChannelManager, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let fd = std::os::fd::IntoRawFd::into_raw_fd(listener);
+        (ChannelManager::start(&config(EXTENSION_ID), Some(fd)).unwrap(), address)
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) -> bool {
+        (0..50).any(|_| condition() || {
+            thread::sleep(Duration::from_millis(20));
+            false
+        })
+    }
+
+    /// Reads `input` as the native host does, returns why it stopped and the forwarded events.
+    fn read_native(input: &[u8]) -> (String, Vec<JsonValue>) {
+        let (event_sender, events) = mpsc::channel();
+        let synced = AtomicBool::new(false);
+        let reason = read_native_messages(input, &ChannelSender::default(), &synced, &event_sender);
+        (reason, events.try_iter().collect())
+    }
+
+    #[test]
+    fn native_messages_round_trip() {
+        let removed = r#"{"type":"removed","tabId":3}"#;
+        let mut input = Vec::new();
+        write_native_message(&mut input, removed).unwrap();
+        write_native_message(&mut input, r#"{"type":"snapshot","tabs":[]}"#).unwrap();
+        assert_eq!(&input[..4], &(removed.len() as u32).to_ne_bytes());
+        assert_eq!(&input[4..4 + removed.len()], removed.as_bytes());
+        let (reason, events) = read_native(&input);
+        assert_eq!(reason, "stdin closed");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["tabId"], 3);
+        assert_eq!(events[1]["type"], "snapshot");
+    }
+
+    #[test]
+    fn oversized_native_message_is_refused() {
+        let mut input = ((MAX_NATIVE_MESSAGE + 1) as u32).to_ne_bytes().to_vec();
+        input.extend_from_slice(b"{}");
+        let (reason, events) = read_native(&input);
+        assert_eq!(reason, format!("message of {} bytes is too large", MAX_NATIVE_MESSAGE + 1));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn truncated_native_message_stops_the_host() {
+        let mut input = 100u32.to_ne_bytes().to_vec();
+        input.extend_from_slice(br#"{"type":"#);
+        let (reason, events) = read_native(&input);
+        assert_ne!(reason, "stdin closed");
+        assert!(events.is_empty());
+        // a length cut short is an ordinary end of input
+        assert_eq!(read_native(&[1, 0]).0, "stdin closed");
+    }
+
+    #[test]
+    fn the_channel_does_not_start_without_an_extension_id() {
+        assert!(ChannelManager::start(&config(""), None).is_err());
+    }
+
+    #[test]
+    fn only_the_configured_extension_may_connect() {
+        let (_channel, address) = start();
+        assert!(connect(&address, "pbcdefghijklmnopabcdefghijklmnop").is_none());
+        assert!(connect(&address, EXTENSION_ID).is_some());
+    }
+
+    #[test]
+    fn a_second_connection_does_not_replace_the_live_one() {
+        let (channel, address) = start();
+        let mut first = connect(&address, EXTENSION_ID).unwrap();
+        assert!(wait_until(|| channel.sender.is_connected()));
+        assert!(connect(&address, EXTENSION_ID).is_none());
+
+        // the first connection still receives the commands
+        assert!(channel.sender.reload(7));
+        assert_eq!(first.read().unwrap(), Message::Text(r#"{"command":"reload","tabId":7}"#.to_string()));
+
+        first.close(None).unwrap();
+        while first.read().is_ok() {}
+        assert!(wait_until(|| !channel.sender.is_connected()));
+        assert!(connect(&address, EXTENSION_ID).is_some());
+    }
+
+    #[test]
+    fn discard_confirmations_are_kept_for_the_next_tick() {
+        let (channel, address) = start();
+        let mut extension = connect(&address, EXTENSION_ID).unwrap();
+        assert!(wait_until(|| channel.sender.is_connected()));
+        assert!(channel.sender.discard(7));
+        assert_eq!(extension.read().unwrap(), Message::Text(r#"{"command":"discard","tabId":7}"#.to_string()));
+        extension.send(Message::Text(r#"{"type":"discarded","tabId":7,"ok":false,"error":"active tab"}"#.to_string())).unwrap();
+        extension.send(Message::Text(r#"{"type":"discarded","tabId":8,"ok":true}"#.to_string())).unwrap();
+        assert!(wait_until(|| channel.sender.acks.lock().unwrap().len() == 2));
+        let acks = channel.sender.take_discard_acks();
+        assert_eq!(acks[&7], Err("active tab".to_string()));
+        assert_eq!(acks[&8], Ok(()));
+        assert!(channel.sender.take_discard_acks().is_empty());
+        // the confirmations are not tab events
+        assert!(channel.events.try_recv().is_err());
+    }
+}
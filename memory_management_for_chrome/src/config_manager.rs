@@ -1,37 +1,573 @@
 use serde::Deserialize;
+
+use crate::escalation_manager::Tier;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
 pub struct ConfigManager {
-    pub rss_limit: i32,
+    pub rss_high_watermark: i32,
+    pub rss_low_watermark: i32,
+    pub rss_reclaim_cooldown: u64,
+    pub pageout_yield: f64,
     pub idel_time_limit: i32,
     pub memory_change_rate: f32,
+    pub memory_change_window: u64,
+    pub memory_change_metric: String,
+    pub ewma_alpha: f64,
     pub reflush_time: u64,
-    pub strategy: String
+    pub startup_timeout: u64,
+    pub snapshot_max_age: u64,
+    pub max_consecutive_failures: u32,
+    pub strategy: String,
+    pub escalation: EscalationConfig,
+    pub window: WindowConfig,
+    pub budget: BudgetConfig,
+    pub group: GroupConfig,
+    pub history: HistoryConfig,
+    pub leak: LeakConfig,
+    pub guardrail: GuardrailConfig,
+    pub verification: VerificationConfig,
+    pub reuse: ReuseConfig,
+    pub pattern: PatternConfig,
+    pub audit: AuditConfig,
+    pub control: ControlConfig,
+    pub channel: ChannelConfig,
+    pub log: LogConfig,
 }
 
 #[derive(Deserialize)]
 struct Config {
-    chrome_memory_manager: ChromeMemoryManager
+    chrome_memory_manager: ChromeMemoryManager,
+    #[serde(default)]
+    escalation: EscalationConfig,
+    #[serde(default)]
+    window: WindowConfig,
+    #[serde(default)]
+    budget: BudgetConfig,
+    #[serde(default)]
+    group: GroupConfig,
+    #[serde(default)]
+    history: HistoryConfig,
+    #[serde(default)]
+    leak: LeakConfig,
+    #[serde(default)]
+    guardrail: GuardrailConfig,
+    #[serde(default)]
+    verification: VerificationConfig,
+    #[serde(default)]
+    reuse: ReuseConfig,
+    #[serde(default)]
+    pattern: PatternConfig,
+    #[serde(default)]
+    audit: AuditConfig,
+    #[serde(default)]
+    control: ControlConfig,
+    #[serde(default)]
+    channel: ChannelConfig,
+    #[serde(default)]
+    log: LogConfig,
 }
 
 #[derive(Deserialize)]
 struct ChromeMemoryManager {
-    rss_limit: i32,
+    rss_limit: Option<Watermark>,
+    rss_high_watermark: Option<Watermark>, // start reclaiming above it, default rss_limit
+    rss_low_watermark: Option<Watermark>, // reclaim until total rss is below it, default rss_high_watermark
+    #[serde(default)]
+    rss_reclaim_cooldown: u64, // second between two reclaim episodes
+    #[serde(default = "default_pageout_yield")]
+    pageout_yield: f64, // share of its rss a paged out tab is expected to give back at first
     idel_time_limit: i32,
     memory_change_rate: f32,
+    #[serde(default = "default_memory_change_window")]
+    memory_change_window: u64, // second
+    #[serde(default = "default_memory_change_metric")]
+    memory_change_metric: String, // mean, stddev, slope, ewma
+    #[serde(default = "default_ewma_alpha")]
+    ewma_alpha: f64,
     reflush_time: u64,
+    #[serde(default = "default_startup_timeout")]
+    startup_timeout: u64, // second to wait for every input before running degraded
+    #[serde(default = "default_snapshot_max_age")]
+    snapshot_max_age: u64, // second, an older log.json is stale
+    #[serde(default = "default_max_consecutive_failures")]
+    max_consecutive_failures: u32, // failed ticks in a row before reclamation is paused
     strategy: String
 }
 
+fn default_startup_timeout() -> u64 {
+    15
+}
+
+fn default_snapshot_max_age() -> u64 {
+    30
+}
+
+fn default_max_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_pageout_yield() -> f64 {
+    0.5
+}
+
+fn default_memory_change_window() -> u64 {
+    30
+}
+
+fn default_memory_change_metric() -> String {
+    "mean".to_string()
+}
+
+fn default_ewma_alpha() -> f64 {
+    0.3
+}
+
+/// A watermark is either an absolute value in KB or a percentage of system memory, e.g. "60%".
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum Watermark {
+    Absolute(i32),
+    Percentage(String),
+}
+
+impl Watermark {
+    fn to_kb(&self, mem_total: i32) -> i32 {
+        match self {
+            Watermark::Absolute(kb) => *kb,
+            Watermark::Percentage(value) => {
+                let percent: f64 = value
+                    .trim()
+                    .trim_end_matches('%')
+                    .trim()
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid watermark: {}", value));
+                (mem_total as f64 * percent / 100.0) as i32
+            }
+        }
+    }
+}
+
+/// Reads MemTotal (KB) from /proc/meminfo.
+fn system_memory_total() -> i32 {
+    fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| {
+            meminfo
+                .lines()
+                .find(|line| line.starts_with("MemTotal:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|value| value.parse().ok())
+        })
+        .expect("Failed to read MemTotal from /proc/meminfo")
+}
+
+/// Dwell time is how long (second) a candidate tab stays in a tier before moving to the next one.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct EscalationConfig {
+    pub enabled: bool,
+    pub observe_dwell: u64,
+    pub pageout_dwell: u64,
+    pub freeze_dwell: u64,
+    pub discard_dwell: u64,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        Self { enabled: true, observe_dwell: 0, pageout_dwell: 30, freeze_dwell: 60, discard_dwell: 120 }
+    }
+}
+
+/// Policies for the active tabs that are not in the foreground, each is "protect" (never
+/// reclaimed, like the foreground tab) or the deepest tier the tab may be taken to.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub visible: String,   // active tab of another window that is not minimized
+    pub minimized: String, // active tab of a minimized window
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self { visible: "protect".to_string(), minimized: "kill".to_string() }
+    }
+}
+
+/// Memory budgets keyed by a URL host pattern, limits are in KB.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub pinned_multiplier: f32, // pinned tabs get tab_limit * pinned_multiplier
+    pub site: Vec<SiteBudget>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self { pinned_multiplier: 1.0, site: Vec::new() }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SiteBudget {
+    pub pattern: String,         // "*" or "*.atlassian.net", matched against the host of the tab url
+    pub tab_limit: Option<i32>,  // any single matching tab above it is reclaimed when in background
+    pub site_limit: Option<i32>, // all matching tabs together
+}
+
+/// Policies keyed by a tab group title pattern, the first matching policy applies.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GroupConfig {
+    pub policy: Vec<GroupPolicy>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GroupPolicy {
+    pub pattern: String,                  // "*" or "On call", matched against the group title, tabs outside a group never match
+    #[serde(default)]
+    pub protect: bool,                    // tabs of the group are never reclaimed
+    pub collapsed_idle_time: Option<u64>, // second, tabs of a collapsed group idle this long are reclaimed
+    pub group_limit: Option<i32>,         // KB, all tabs of one group together
+}
+
+/// On-disk per-tab history, durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    pub path: String,
+    pub segment_max_bytes: u64,
+    pub downsample_after: u64,    // segments older than it keep one sample per downsample_interval
+    pub downsample_interval: u64,
+    pub retention: u64,           // segments older than it are deleted
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "history".to_string(),
+            segment_max_bytes: 4 * 1024 * 1024,
+            downsample_after: 3600,
+            downsample_interval: 60,
+            retention: 7 * 86400,
+        }
+    }
+}
+
+/// Leak detection over the rss history of each renderer, durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LeakConfig {
+    pub horizon: u64,
+    pub sample_interval: u64,
+    pub min_slope: f64,     // KB per second
+    pub min_r_squared: f64,
+    pub notify: bool,       // desktop notification through notify-send
+}
+
+impl Default for LeakConfig {
+    fn default() -> Self {
+        Self { horizon: 3600, sample_interval: 30, min_slope: 10.0, min_r_squared: 0.8, notify: true }
+    }
+}
+
+/// Limits on releasing (discarding or killing) renderers, whatever the strategy asks for.
+/// A limit of 0 disables it, durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GuardrailConfig {
+    pub max_kills_per_minute: usize,
+    pub max_kills_per_hour: usize,
+    pub min_live_tabs: usize,
+    pub min_inactive_time: u64,    // since the tab was last active, before any reclaim action
+    pub breaker_kill_rate: usize,  // distinct tabs asked to be released within a minute
+    pub breaker_cooldown: u64,     // 0 keeps the breaker open until restart
+    pub notify: bool,              // desktop notification when the breaker opens
+}
+
+impl Default for GuardrailConfig {
+    fn default() -> Self {
+        Self {
+            max_kills_per_minute: 5,
+            max_kills_per_hour: 30,
+            min_live_tabs: 3,
+            min_inactive_time: 60,
+            breaker_kill_rate: 10,
+            breaker_cooldown: 3600,
+            notify: true,
+        }
+    }
+}
+
+/// How reclaim actions are followed up, durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct VerificationConfig {
+    pub window: u64,         // an action is judged this long after it was taken
+    pub respawn_window: u64, // respawns of a tab counted over this period
+    pub respawn_limit: usize, // respawns within respawn_window that make a reload loop
+    pub backoff: u64,        // first back off of a looping tab, doubled on every new loop
+    pub max_backoff: u64,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self { window: 30, respawn_window: 600, respawn_limit: 2, backoff: 600, max_backoff: 86400 }
+    }
+}
+
+/// Weights of the expected reuse score used to pick the victims of the rss_limit strategy,
+/// durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ReuseConfig {
+    pub recency_weight: f64,
+    pub frequency_weight: f64,
+    pub age_weight: f64,
+    pub reload_cost_weight: f64,
+    pub priority_weight: f64,
+    pub pattern_weight: f64,
+    pub recency_half_life: u64, // since the tab was last active
+    pub age_half_life: u64,     // since the tab was first seen
+    pub reload_cost_rss: i32,   // KB, tabs above it are the costliest to reload
+    pub priority: Vec<SitePriority>,
+}
+
+impl Default for ReuseConfig {
+    fn default() -> Self {
+        Self {
+            recency_weight: 1.0,
+            frequency_weight: 1.0,
+            age_weight: 0.5,
+            reload_cost_weight: 0.5,
+            priority_weight: 1.0,
+            pattern_weight: 1.0,
+            recency_half_life: 1800,
+            age_half_life: 86400,
+            reload_cost_rss: 500_000,
+            priority: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SitePriority {
+    pub pattern: String, // matched against the host of the tab url like a budget, the first match wins
+    pub priority: f64,   // 1.0 keeps the site around, a negative value sacrifices it first
+}
+
+/// Activation patterns learned per site by hour of the week, durations are in second.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PatternConfig {
+    pub path: String,
+    pub half_life: u64,           // for an activation to count half, 0 never forgets
+    pub lookahead: u64,           // hours ahead a site is expected in
+    pub protect_likelihood: f64,  // tabs at or above it are never reclaimed, 0 disables it
+    pub min_history: u64,         // second since a site was first seen before protect_likelihood applies to it
+    pub save_interval: u64,
+}
+
+impl Default for PatternConfig {
+    fn default() -> Self {
+        Self {
+            path: "patterns.json".to_string(),
+            half_life: 28 * 86400,
+            lookahead: 1,
+            protect_likelihood: 0.8,
+            min_history: 7 * 86400,
+            save_interval: 300,
+        }
+    }
+}
+
+/// Audit log of every reclaim action, rotated once it grows over max_bytes.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub path: String,
+    pub max_bytes: u64,
+    pub max_files: u32, // rotated files kept next to the current one
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { path: "audit.jsonl".to_string(), max_bytes: 1024 * 1024, max_files: 5 }
+    }
+}
+
+/// Local control API, an empty socket path means $XDG_RUNTIME_DIR/chrome-memory-manager.sock.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    pub socket: String,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self { enabled: true, socket: String::new() }
+    }
+}
+
+/// WebSocket channel with the extension, it replaces the tab info server and log.json when enabled.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ChannelConfig {
+    pub enabled: bool,
+    pub address: String,      // used unless systemd passes the ingestion socket
+    pub extension_id: String, // the only extension that may connect, the channel does not start without it
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self { enabled: false, address: "127.0.0.1:8080".to_string(), extension_id: String::new() }
+    }
+}
+
+/// Log level is one of off, error, warn, info, debug, trace.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    pub level: String,
+    pub format: String,                   // auto, human, json, journald
+    pub modules: HashMap<String, String>, // per-module level, e.g. tab_manager = "debug"
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".to_string(), format: "auto".to_string(), modules: HashMap::new() }
+    }
+}
+
 impl ConfigManager {
     pub fn new(config_path: &Path) -> Self {
         let context = fs::read_to_string(config_path).expect("Failed to read config file");
-        let config: Config = toml::from_str(&context).expect("Failed to parse config file");
-        Self {  rss_limit: config.chrome_memory_manager.rss_limit, 
+        Self::parse(&context)
+    }
+
+    /// Smallest valid manager.toml, `extra` is appended to its `[chrome_memory_manager]` section
+    /// and may open other sections. Nothing is read from or kept on disk.
+    #[cfg(test)]
+    pub fn minimal(extra: &str) -> Self {
+        Self::parse(&format!(
+            "[pattern]
+            path = \"/nonexistent/patterns.json\"
+
+            [chrome_memory_manager]
+            rss_high_watermark = 1000
+            idel_time_limit = 600
+            memory_change_rate = 100.0
+            reflush_time = 5
+            max_consecutive_failures = 3
+            strategy = \"rss_limit\"
+            {}",
+            extra
+        ))
+    }
+
+    pub fn parse(context: &str) -> Self {
+        let config: Config = toml::from_str(context).expect("Failed to parse config file");
+        let chrome_memory_manager = &config.chrome_memory_manager;
+        let mem_total = system_memory_total();
+        let rss_high_watermark = chrome_memory_manager
+            .rss_high_watermark
+            .as_ref()
+            .or(chrome_memory_manager.rss_limit.as_ref())
+            .expect("Either rss_high_watermark or rss_limit has to be set")
+            .to_kb(mem_total);
+        let rss_low_watermark = chrome_memory_manager
+            .rss_low_watermark
+            .as_ref()
+            .map_or(rss_high_watermark, |watermark| watermark.to_kb(mem_total));
+        assert!(chrome_memory_manager.reflush_time > 0, "reflush_time has to be at least 1 second");
+        assert!(chrome_memory_manager.max_consecutive_failures > 0, "max_consecutive_failures has to be at least 1");
+        assert!(chrome_memory_manager.memory_change_window > 0, "memory_change_window has to be at least 1 second");
+        assert!(
+            chrome_memory_manager.ewma_alpha > 0.0 && chrome_memory_manager.ewma_alpha <= 1.0,
+            "ewma_alpha has to be above 0 and at most 1"
+        );
+        assert!(
+            ["mean", "stddev", "slope", "ewma"].contains(&chrome_memory_manager.memory_change_metric.as_str()),
+            "Invalid memory_change_metric: {}", chrome_memory_manager.memory_change_metric
+        );
+        assert!(rss_low_watermark <= rss_high_watermark, "rss_low_watermark has to be below rss_high_watermark");
+        assert!(
+            (0.0..=1.0).contains(&chrome_memory_manager.pageout_yield),
+            "pageout_yield has to be between 0 and 1"
+        );
+        for policy in [&config.window.visible, &config.window.minimized] {
+            assert!(
+                policy == "protect" || Tier::parse(policy).is_some(),
+                "Invalid window policy: {}, expected protect or a tier", policy
+            );
+        }
+
+        Self {  rss_high_watermark,
+                rss_low_watermark,
+                rss_reclaim_cooldown: config.chrome_memory_manager.rss_reclaim_cooldown,
+                pageout_yield: config.chrome_memory_manager.pageout_yield,
                 idel_time_limit: config.chrome_memory_manager.idel_time_limit,
                 memory_change_rate: config.chrome_memory_manager.memory_change_rate,
-                reflush_time: config.chrome_memory_manager.reflush_time, 
-                strategy:  config.chrome_memory_manager.strategy }
-            }            
-}
\ No newline at end of file
+                memory_change_window: config.chrome_memory_manager.memory_change_window,
+                memory_change_metric: config.chrome_memory_manager.memory_change_metric.clone(),
+                ewma_alpha: config.chrome_memory_manager.ewma_alpha,
+                reflush_time: config.chrome_memory_manager.reflush_time,
+                startup_timeout: config.chrome_memory_manager.startup_timeout,
+                snapshot_max_age: config.chrome_memory_manager.snapshot_max_age,
+                max_consecutive_failures: config.chrome_memory_manager.max_consecutive_failures,
+                strategy:  config.chrome_memory_manager.strategy,
+                escalation: config.escalation,
+                window: config.window,
+                budget: config.budget,
+                group: config.group,
+                history: config.history,
+                leak: config.leak,
+                guardrail: config.guardrail,
+                verification: config.verification,
+                reuse: config.reuse,
+                pattern: config.pattern,
+                audit: config.audit,
+                control: config.control,
+                channel: config.channel,
+                log: config.log }
+            }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(extra: &str) -> ConfigManager {
+        ConfigManager::minimal(extra)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config_manager = parse("");
+        assert_eq!(config_manager.memory_change_window, 30);
+        assert_eq!(config_manager.ewma_alpha, 0.3);
+        assert_eq!(parse("ewma_alpha = 1.0").ewma_alpha, 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ewma_alpha")]
+    fn ewma_alpha_of_zero_is_refused() {
+        parse("ewma_alpha = 0.0");
+    }
+
+    #[test]
+    #[should_panic(expected = "ewma_alpha")]
+    fn ewma_alpha_above_one_is_refused() {
+        parse("ewma_alpha = 1.5");
+    }
+
+    #[test]
+    #[should_panic(expected = "memory_change_window")]
+    fn empty_memory_change_window_is_refused() {
+        parse("memory_change_window = 0");
+    }
+}
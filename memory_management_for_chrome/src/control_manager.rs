@@ -0,0 +1,426 @@
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audit_manager::AuditManager;
+use crate::config_manager::ControlConfig;
+use crate::escalation_manager::Tier;
+use crate::history_manager::{self, HistoryFilter};
+use crate::memory_manager::{MemoryManager, STRATEGIES};
+use crate::tab_manager::TabProcessInfo;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+/// A request read by a connection thread, answered by the main loop through `reply`.
+struct ControlRequest {
+    method: String,
+    params: Value,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+/// What a control request may read or change, borrowed from the main loop between two ticks.
+pub struct ControlContext<'a> {
+    pub memory_manager: &'a mut MemoryManager,
+    pub tabs: &'a [TabProcessInfo], // tabs of the last successful tick
+    pub audit_manager: &'a AuditManager,
+    pub strategy: &'a mut String,
+}
+
+/// JSON-RPC 2.0 over a Unix socket, one request per line.
+///
+/// The socket is only accessible to its owner and connections from another user are dropped.
+/// Connection threads forward requests to the main loop, which answers them while it waits
+/// for the next tick, so requests never race with reclamation.
+pub struct ControlManager {
+    pub path: PathBuf,
+    requests: Receiver<ControlRequest>,
+}
+
+impl ControlManager {
+    pub fn socket_path(config: &ControlConfig) -> PathBuf {
+        if !config.socket.is_empty() {
+            return PathBuf::from(&config.socket);
+        }
+        match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => Path::new(&runtime_dir).join("chrome-memory-manager.sock"),
+            None => PathBuf::from("control.sock"),
+        }
+    }
+
+    pub fn start(config: &ControlConfig) -> io::Result<Self> {
+        let path = Self::socket_path(config);
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another manager listens on {}", path.display()),
+            ));
+        }
+        // a socket left behind by a manager that did not shut down cleanly
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || handle_connection(stream, sender));
+                    }
+                    Err(err) => warn!("Control connection failed: {}", err),
+                }
+            }
+        });
+        info!("Control API listening on {}", path.display());
+        Ok(Self { path, requests })
+    }
+
+    /// Answers requests until `timeout` expires, this replaces the sleep between two ticks.
+    pub fn serve(&self, timeout: Duration, mut context: ControlContext) {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.requests.recv_timeout(remaining) {
+                Ok(request) => {
+                    debug!("Control request: {} {}", request.method, request.params);
+                    let result = dispatch(&request.method, &request.params, &mut context);
+                    let _ = request.reply.send(result);
+                }
+                Err(RecvTimeoutError::Timeout) => return,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(remaining);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn cleanup(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn handle_connection(stream: UnixStream, requests: Sender<ControlRequest>) {
+    // SAFETY: getuid cannot fail
+    let uid = unsafe { libc::getuid() };
+    if peer_uid(&stream) != Some(uid) {
+        warn!("Refusing control connection from another user");
+        return;
+    }
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<Value>(&line) {
+            Err(err) => (Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string()))),
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                match request.get("method").and_then(Value::as_str) {
+                    None => (id, Err(RpcError::new(INVALID_REQUEST, "missing method"))),
+                    Some(method) => {
+                        let (reply, result) = mpsc::channel();
+                        let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+                        if requests.send(ControlRequest { method: method.to_string(), params, reply }).is_err() {
+                            return;
+                        }
+                        let result = result
+                            .recv()
+                            .unwrap_or_else(|_| Err(RpcError::new(SERVER_ERROR, "manager is shutting down")));
+                        (id, result)
+                    }
+                }
+            }
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+            Err(err) => json!({ "jsonrpc": "2.0", "error": { "code": err.code, "message": err.message }, "id": id }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Uid of the process on the other end of the socket.
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut credentials = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: SO_PEERCRED fills a ucred of the given length
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    (ret == 0).then_some(credentials.uid)
+}
+
+fn tab_id_param(params: &Value) -> Result<i32, RpcError> {
+    params
+        .get("tab_id")
+        .and_then(Value::as_i64)
+        .map(|tab_id| tab_id as i32)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "tab_id is required"))
+}
+
+fn tab_json(tab: &TabProcessInfo, memory_manager: &MemoryManager) -> Value {
+    json!({
+        "tab_id": tab.tab_inner_id,
+        "chrome_tab_id": tab.chrome_tab_id,
+        "title": tab.tab_name,
+        "url": tab.url,
+        "pid": tab.tab_process_id,
+        "rss": tab.tab_rss,
+        "is_active": tab.is_active,
+        "pinned": tab.pinned,
+        "window_id": tab.window_id,
+        "index": tab.index,
+        "visibility": tab.visibility.as_str(),
+        "group_id": tab.group_id,
+        "group_title": tab.group_title,
+        "group_collapsed": tab.group_collapsed,
+        "audible": tab.audible,
+        "muted": tab.muted,
+        "media_playing": tab.media_playing,
+        "in_use": tab.in_use,
+        "tier": memory_manager.escalation_manager.tier_of(tab.tab_inner_id).as_str(),
+        "protected": memory_manager.protected_tabs.contains(&tab.tab_inner_id),
+        "reuse_score": memory_manager.reuse_manager.score_of(tab.tab_inner_id).score,
+    })
+}
+
+fn find_tab(tabs: &[TabProcessInfo], tab_id: i32) -> Result<&TabProcessInfo, RpcError> {
+    tabs.iter()
+        .find(|tab| tab.tab_inner_id == tab_id)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("unknown tab {}", tab_id)))
+}
+
+fn dispatch(method: &str, params: &Value, context: &mut ControlContext) -> Result<Value, RpcError> {
+    let memory_manager = &mut *context.memory_manager;
+    match method {
+        "list_tabs" => {
+            let tabs: Vec<Value> = context.tabs.iter().map(|tab| tab_json(tab, memory_manager)).collect();
+            Ok(json!(tabs))
+        }
+        "snapshot" => {
+            let victims = memory_manager.upcoming_victims(context.tabs, context.strategy);
+            let timestamp = history_manager::unix_now();
+            let tabs: Vec<Value> = context
+                .tabs
+                .iter()
+                .map(|tab| {
+                    let history: Vec<i32> = memory_manager
+                        .leak_manager
+                        .process_rss_history
+                        .get(&tab.process_key())
+                        .map(|series| series.samples.iter().map(|&(_, rss)| rss).collect())
+                        .unwrap_or_default();
+                    let mut value = tab_json(tab, memory_manager);
+                    value["idle_time"] = json!(tab.idle_time(timestamp));
+                    value["change_rate"] = json!(memory_manager.change_rate(tab));
+                    value["history"] = json!(history);
+                    value["upcoming_victim"] = json!(victims.contains(&tab.tab_inner_id));
+                    value
+                })
+                .collect();
+            Ok(json!({
+                "strategy": context.strategy,
+                "total_rss": context.tabs.iter().map(|tab| tab.tab_rss).sum::<i32>(),
+                "browser_rss": memory_manager.browser_rss,
+                "rss_high_watermark": memory_manager.rss_high_watermark,
+                "rss_low_watermark": memory_manager.rss_low_watermark,
+                "status": memory_manager.status(),
+                "tabs": tabs,
+            }))
+        }
+        "status" => {
+            let mut status = memory_manager.status();
+            status["strategy"] = json!(context.strategy);
+            Ok(status)
+        }
+        "kill" | "discard" | "freeze" => {
+            let tab_id = tab_id_param(params)?;
+            let tier = match method {
+                "kill" => Tier::Kill,
+                "discard" => Tier::Discard,
+                _ => Tier::Freeze,
+            };
+            let tab = find_tab(context.tabs, tab_id)?;
+            if tab.tab_process_id == -1 {
+                return Err(RpcError::new(SERVER_ERROR, format!("tab {} has no renderer process", tab_id)));
+            }
+            if memory_manager.protected_tabs.contains(&tab_id) {
+                return Err(RpcError::new(SERVER_ERROR, format!("tab {} is protected, unprotect it first", tab_id)));
+            }
+            let record = memory_manager.manual_action(tab, tier);
+            if let Err(err) = context.audit_manager.append(std::slice::from_ref(&record)) {
+                warn!("Failed to write audit log: {}", err);
+            }
+            match &record.error {
+                Some(error) => Err(RpcError::new(SERVER_ERROR, error.clone())),
+                None => Ok(json!(record)),
+            }
+        }
+        "reload" => {
+            let tab_id = tab_id_param(params)?;
+            let tab = find_tab(context.tabs, tab_id)?;
+            if !memory_manager.channel.reload(tab.chrome_tab_id) {
+                return Err(RpcError::new(SERVER_ERROR, "reload needs the extension connected over the channel"));
+            }
+            info!("Tab ID: {} reloaded on request", tab_id);
+            Ok(json!({ "tab_id": tab_id, "reloaded": true }))
+        }
+        "protect" | "unprotect" => {
+            let tab_id = tab_id_param(params)?;
+            if method == "protect" {
+                memory_manager.protected_tabs.insert(tab_id);
+            } else {
+                memory_manager.protected_tabs.remove(&tab_id);
+            }
+            // Chrome's own tab discarding leaves a protected tab alone too
+            if let Some(tab) = context.tabs.iter().find(|tab| tab.tab_inner_id == tab_id) {
+                memory_manager.channel.protect(tab.chrome_tab_id, method == "protect");
+            }
+            info!("Tab ID: {} is {}", tab_id, if method == "protect" { "protected" } else { "no longer protected" });
+            Ok(json!({ "tab_id": tab_id, "protected": method == "protect" }))
+        }
+        "pause" => {
+            memory_manager.paused_by_user = true;
+            info!("Reclamation paused through the control API");
+            Ok(json!({ "paused": true }))
+        }
+        "resume" => {
+            memory_manager.paused_by_user = false;
+            memory_manager.guardrail_manager.close_breaker();
+            info!("Reclamation resumed through the control API");
+            Ok(json!({ "paused": false }))
+        }
+        "set_strategy" => {
+            let strategy = params
+                .get("strategy")
+                .and_then(Value::as_str)
+                .filter(|strategy| STRATEGIES.contains(strategy))
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("strategy has to be one of {}", STRATEGIES.join(", "))))?;
+            info!("Switching strategy from {} to {}", context.strategy, strategy);
+            *context.strategy = strategy.to_string();
+            Ok(json!({ "strategy": strategy }))
+        }
+        "audit" => {
+            let limit = params.get("limit").and_then(Value::as_u64).unwrap_or(20) as usize;
+            let records = context
+                .audit_manager
+                .query(&HistoryFilter::default())
+                .map_err(|err| RpcError::new(SERVER_ERROR, err.to_string()))?;
+            let skip = records.len().saturating_sub(limit);
+            Ok(json!(records[skip..]))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method {}", method))),
+    }
+}
+
+/// Client side of the ctl command: sends one request to a running manager.
+pub fn call(path: &Path, method: &str, params: Value) -> io::Result<Result<Value, RpcError>> {
+    let stream = UnixStream::connect(path)?;
+    let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    writeln!(&stream, "{}", request)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response: Value =
+        serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if let Some(error) = response.get("error") {
+        return Ok(Err(RpcError {
+            code: error.get("code").and_then(Value::as_i64).unwrap_or(SERVER_ERROR),
+            message: error.get("message").and_then(Value::as_str).unwrap_or_default().to_string(),
+        }));
+    }
+    Ok(Ok(response.get("result").cloned().unwrap_or(Value::Null)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::{AuditConfig, ConfigManager};
+
+    /// Answers `method` against the given tabs, with an audit log in a directory of its own.
+    fn call(memory_manager: &mut MemoryManager, strategy: &mut String, tabs: &[TabProcessInfo], method: &str, params: Value) -> Result<Value, RpcError> {
+        let dir = std::env::temp_dir().join(format!("control-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("audit-{}.jsonl", method)).to_string_lossy().into_owned();
+        let audit_manager = AuditManager::new(&AuditConfig { path, ..AuditConfig::default() });
+        let mut context = ControlContext { memory_manager, tabs, audit_manager: &audit_manager, strategy };
+        dispatch(method, &params, &mut context)
+    }
+
+    #[test]
+    fn set_strategy_rejects_unknown_names() {
+        let mut memory_manager = MemoryManager::new(&ConfigManager::minimal(""));
+        let mut strategy = "rss_limit".to_string();
+        let error = call(&mut memory_manager, &mut strategy, &[], "set_strategy", json!({ "strategy": "random" })).unwrap_err();
+        assert_eq!(error.code, INVALID_PARAMS);
+        assert_eq!(strategy, "rss_limit");
+        let result = call(&mut memory_manager, &mut strategy, &[], "set_strategy", json!({ "strategy": "leak" })).unwrap();
+        assert_eq!(result["strategy"], "leak");
+        assert_eq!(strategy, "leak");
+    }
+
+    #[test]
+    fn protected_tab_is_not_killed_on_request() {
+        let mut memory_manager = MemoryManager::new(&ConfigManager::minimal(""));
+        let mut strategy = "rss_limit".to_string();
+        // no such process, a kill that got through would fail rather than hit anything
+        let tabs = [TabProcessInfo { tab_inner_id: 7, tab_process_id: i32::MAX, tab_rss: 1000, ..Default::default() }];
+        call(&mut memory_manager, &mut strategy, &tabs, "protect", json!({ "tab_id": 7 })).unwrap();
+        let error = call(&mut memory_manager, &mut strategy, &tabs, "kill", json!({ "tab_id": 7 })).unwrap_err();
+        assert_eq!(error.message, "tab 7 is protected, unprotect it first");
+        assert_eq!(memory_manager.escalation_manager.tier_of(7), Tier::Observe);
+
+        call(&mut memory_manager, &mut strategy, &tabs, "unprotect", json!({ "tab_id": 7 })).unwrap();
+        let error = call(&mut memory_manager, &mut strategy, &tabs, "kill", json!({ "tab_id": 7 })).unwrap_err();
+        assert_ne!(error.message, "tab 7 is protected, unprotect it first");
+    }
+
+    #[test]
+    fn unknown_method_is_reported() {
+        let mut memory_manager = MemoryManager::new(&ConfigManager::minimal(""));
+        let mut strategy = "rss_limit".to_string();
+        let error = call(&mut memory_manager, &mut strategy, &[], "reboot", json!({})).unwrap_err();
+        assert_eq!(error.code, METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config_manager::EscalationConfig;
+use crate::process_handle::ProcessKey;
+
+/// Reclamation tiers a tab walks through, from the least to the most disruptive action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    Observe,
+    Pageout,
+    Freeze,
+    Discard,
+    Kill,
+}
+
+impl Tier {
+    pub fn next(self) -> Option<Tier> {
+        match self {
+            Tier::Observe => Some(Tier::Pageout),
+            Tier::Pageout => Some(Tier::Freeze),
+            Tier::Freeze => Some(Tier::Discard),
+            Tier::Discard => Some(Tier::Kill),
+            Tier::Kill => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tier::Observe => "observe",
+            Tier::Pageout => "pageout",
+            Tier::Freeze => "freeze",
+            Tier::Discard => "discard",
+            Tier::Kill => "kill",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Tier> {
+        match name {
+            "observe" => Some(Tier::Observe),
+            "pageout" => Some(Tier::Pageout),
+            "freeze" => Some(Tier::Freeze),
+            "discard" => Some(Tier::Discard),
+            "kill" => Some(Tier::Kill),
+            _ => None,
+        }
+    }
+
+    /// The renderer process no longer exists once a tab reaches these tiers.
+    pub fn is_released(&self) -> bool {
+        matches!(self, Tier::Discard | Tier::Kill)
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct TabEscalation {
+    pub tier: Tier,
+    pub since: u64, // elapsed time the tab entered the current tier
+    pub process: ProcessKey,
+}
+
+/// Per-tab escalation state machine shared by all strategies.
+///
+/// A strategy only decides whether a tab is a reclaim candidate on this tick; the ladder
+/// decides how far to go. Idle candidates have to stay in a tier for its dwell time before
+/// moving up, candidates under memory pressure move up one tier every tick.
+pub struct EscalationManager {
+    pub enabled: bool,
+    pub pageout_supported: bool, // false once process_madvise turned out to be unavailable
+    pub tier_dwell_time: HashMap<Tier, u64>,
+    pub tab_tier_map: HashMap<i32, TabEscalation>, // tab_inner_id -> escalation state
+}
+
+impl EscalationManager {
+    pub fn new(config: &EscalationConfig) -> Self {
+        let tier_dwell_time = HashMap::from([
+            (Tier::Observe, config.observe_dwell),
+            (Tier::Pageout, config.pageout_dwell),
+            (Tier::Freeze, config.freeze_dwell),
+            (Tier::Discard, config.discard_dwell),
+        ]);
+        Self { enabled: config.enabled, pageout_supported: true, tier_dwell_time, tab_tier_map: HashMap::new() }
+    }
+
+    /// Tier after `tier` on the ladder, past the pageout tier when the kernel does not allow it.
+    fn next_tier(&self, tier: Tier) -> Option<Tier> {
+        match tier.next()? {
+            Tier::Pageout if !self.pageout_supported => Tier::Pageout.next(),
+            next => Some(next),
+        }
+    }
+
+    pub fn tier_of(&self, tab_inner_id: i32) -> Tier {
+        self.tab_tier_map.get(&tab_inner_id).map_or(Tier::Observe, |state| state.tier)
+    }
+
+    /// The tier `escalate` would move the tab to right now, without changing anything.
+    pub fn pending_tier(&self, process: ProcessKey, elapsed_time: u64, under_pressure: bool) -> Option<Tier> {
+        let state = self.current(process);
+        let tier = state.map_or(Tier::Observe, |state| state.tier);
+        if !self.enabled {
+            return (tier != Tier::Kill).then_some(Tier::Kill);
+        }
+        let next = self.next_tier(tier)?;
+        let dwell = state.map_or(0, |state| elapsed_time.saturating_sub(state.since));
+        let dwell_limit = *self.tier_dwell_time.get(&tier).unwrap_or(&0);
+        (under_pressure || dwell >= dwell_limit).then_some(next)
+    }
+
+    /// Moves a candidate tab at most one tier up, returns the new tier if it changed.
+    /// With the ladder disabled every candidate goes straight to `Tier::Kill`. The dwell time
+    /// is measured from `elapsed_time`, so it does not depend on how often a strategy runs.
+    pub fn escalate(&mut self, process: ProcessKey, elapsed_time: u64, under_pressure: bool) -> Option<Tier> {
+        if self.current(process).is_none() {
+            self.tab_tier_map.insert(process.tab_inner_id, TabEscalation { tier: Tier::Observe, since: elapsed_time, process });
+        }
+        let next = self.pending_tier(process, elapsed_time, under_pressure)?;
+        let state = self.tab_tier_map.get_mut(&process.tab_inner_id)?;
+        state.tier = next;
+        state.since = elapsed_time;
+        Some(next)
+    }
+
+    /// Puts a tab straight into a tier, for actions requested by the user.
+    pub fn set_tier(&mut self, process: ProcessKey, tier: Tier, elapsed_time: u64) {
+        self.tab_tier_map.insert(process.tab_inner_id, TabEscalation { tier, since: elapsed_time, process });
+    }
+
+    /// State of the tab if it still belongs to this renderer. A tab served by a new renderer,
+    /// after a crash or a reload, starts over from `Tier::Observe`.
+    fn current(&self, process: ProcessKey) -> Option<&TabEscalation> {
+        self.tab_tier_map
+            .get(&process.tab_inner_id)
+            .filter(|state| state.process == process || process.pid == -1)
+    }
+
+    /// Drops a reactivated tab back to `Tier::Observe`, returns the tier it was in.
+    pub fn deescalate(&mut self, tab_inner_id: i32) -> Option<TabEscalation> {
+        self.tab_tier_map
+            .remove(&tab_inner_id)
+            .filter(|state| state.tier != Tier::Observe)
+    }
+
+    /// Renderers currently stopped by the freeze tier, they have to be resumed before shutdown.
+    pub fn frozen_processes(&self) -> Vec<ProcessKey> {
+        self.tab_tier_map
+            .values()
+            .filter(|state| state.tier == Tier::Freeze)
+            .map(|state| state.process)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROCESS: ProcessKey = ProcessKey { tab_inner_id: 1, pid: 100, start_time: 5 };
+
+    fn manager() -> EscalationManager {
+        EscalationManager::new(&EscalationConfig::default())
+    }
+
+    #[test]
+    fn dwell_does_not_depend_on_how_often_escalate_runs() {
+        // pageout_dwell is 30s: once per second or once per 30s window reach freeze at the same time
+        for period in [1, 7, 30] {
+            let mut manager = manager();
+            assert_eq!(manager.escalate(PROCESS, 0, false), Some(Tier::Pageout));
+            let mut time = 0;
+            while manager.tier_of(1) == Tier::Pageout {
+                time += period;
+                manager.escalate(PROCESS, time, false);
+            }
+            assert_eq!(manager.tier_of(1), Tier::Freeze);
+            assert!((30..30 + period).contains(&time), "period {} reached freeze at {}", period, time);
+        }
+    }
+
+    #[test]
+    fn pressure_skips_the_dwell_time() {
+        let mut manager = manager();
+        assert_eq!(manager.escalate(PROCESS, 0, true), Some(Tier::Pageout));
+        assert_eq!(manager.pending_tier(PROCESS, 0, false), None);
+        assert_eq!(manager.pending_tier(PROCESS, 0, true), Some(Tier::Freeze));
+        assert_eq!(manager.escalate(PROCESS, 0, true), Some(Tier::Freeze));
+    }
+
+    #[test]
+    fn new_renderer_starts_over() {
+        let mut manager = manager();
+        manager.set_tier(PROCESS, Tier::Freeze, 0);
+        let respawned = ProcessKey { pid: 200, ..PROCESS };
+        assert_eq!(manager.escalate(respawned, 100, false), Some(Tier::Pageout));
+    }
+
+    #[test]
+    fn disabled_ladder_kills_right_away() {
+        let mut manager = manager();
+        manager.enabled = false;
+        assert_eq!(manager.escalate(PROCESS, 0, false), Some(Tier::Kill));
+        assert_eq!(manager.escalate(PROCESS, 10, false), None);
+    }
+}
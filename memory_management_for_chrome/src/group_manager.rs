@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::budget_manager::matches_pattern;
+use crate::config_manager::{GroupConfig, GroupPolicy};
+use crate::tab_manager::TabProcessInfo;
+
+/// A tab picked by a group policy, `under_pressure` skips the dwell time of the escalation tiers.
+pub struct GroupVictim {
+    pub tab: TabProcessInfo,
+    pub reason: String,
+    pub under_pressure: bool,
+}
+
+/// Applies the tab group policies from the `[group]` section of manager.toml: protected groups,
+/// collapsed groups reclaimed once idle and per-group memory budgets.
+pub struct GroupManager {
+    pub policies: Vec<GroupPolicy>,
+    pub tab_group_violation: HashMap<i32, String>, // tab_inner_id -> broken group policy, refreshed every tick
+}
+
+impl GroupManager {
+    pub fn new(config: &GroupConfig) -> Self {
+        Self {
+            policies: config.policy.clone(),
+            tab_group_violation: HashMap::new(),
+        }
+    }
+
+    /// First policy whose pattern matches the title of the group of the tab, None outside a group.
+    pub fn policy_of(&self, tab: &TabProcessInfo) -> Option<&GroupPolicy> {
+        if tab.group_id == -1 {
+            return None;
+        }
+        self.policies.iter().find(|policy| matches_pattern(&policy.pattern, &tab.group_title))
+    }
+
+    pub fn is_protected(&self, tab: &TabProcessInfo) -> bool {
+        self.policy_of(tab).is_some_and(|policy| policy.protect)
+    }
+
+    /// Returns the reclaimable tabs a group policy asks to reclaim, together with the reason.
+    /// Every tab breaking a policy is recorded in `tab_group_violation`, reclaimable or not.
+    pub fn check(&mut self, tabs: &[TabProcessInfo], is_reclaimable: impl Fn(&TabProcessInfo) -> bool, timestamp: u64) -> Vec<GroupVictim> {
+        self.tab_group_violation.clear();
+        let mut victims: Vec<GroupVictim> = Vec::new();
+
+        // group id -> live tabs of the group, a group is only ever matched by one policy
+        let mut groups: BTreeMap<i32, Vec<&TabProcessInfo>> = BTreeMap::new();
+        for tab in tabs.iter().filter(|tab| tab.tab_process_id != -1 && tab.group_id != -1) {
+            groups.entry(tab.group_id).or_default().push(tab);
+        }
+
+        for group_tabs in groups.values() {
+            let Some(policy) = self.policy_of(group_tabs[0]).cloned() else {
+                continue;
+            };
+            if policy.protect {
+                continue;
+            }
+            let name = group_name(group_tabs[0]);
+
+            if let Some(collapsed_idle_time) = policy.collapsed_idle_time {
+                for tab in group_tabs.iter().filter(|tab| tab.group_collapsed) {
+                    let idle_time = tab.idle_time(timestamp);
+                    if idle_time < collapsed_idle_time {
+                        continue;
+                    }
+                    let reason = format!("collapsed group {} idle for {}s", name, idle_time);
+                    self.tab_group_violation.insert(tab.tab_inner_id, reason.clone());
+                    if is_reclaimable(tab) {
+                        victims.push(GroupVictim { tab: (*tab).clone(), reason, under_pressure: false });
+                    }
+                }
+            }
+
+            if let Some(group_limit) = policy.group_limit {
+                let group_rss: i32 = group_tabs.iter().map(|tab| tab.tab_rss).sum();
+                if group_rss <= group_limit {
+                    continue;
+                }
+                let reason = format!("group budget of {} exceeded: {} KB > {} KB", name, group_rss, group_limit);
+                for tab in group_tabs.iter() {
+                    self.tab_group_violation.entry(tab.tab_inner_id).or_insert_with(|| reason.clone());
+                }
+
+                // release the biggest reclaimable tabs of the group until it fits again
+                let mut background: Vec<&&TabProcessInfo> = group_tabs.iter().filter(|tab| is_reclaimable(tab)).collect();
+                background.sort_by_key(|tab| std::cmp::Reverse(tab.tab_rss));
+                let mut remaining = group_rss - group_limit;
+                for tab in background {
+                    if remaining <= 0 {
+                        break;
+                    }
+                    remaining -= tab.tab_rss;
+                    victims.push(GroupVictim { tab: (**tab).clone(), reason: reason.clone(), under_pressure: true });
+                }
+            }
+        }
+
+        // a tab of a collapsed group over its budget is reclaimed once, under pressure
+        victims.sort_by_key(|victim| !victim.under_pressure);
+        let mut seen = HashSet::new();
+        victims.retain(|victim| seen.insert(victim.tab.tab_inner_id));
+        victims
+    }
+}
+
+/// Quoted title of the group of a tab, or its id for an untitled group.
+fn group_name(tab: &TabProcessInfo) -> String {
+    if tab.group_title.is_empty() {
+        format!("#{}", tab.group_id)
+    } else {
+        format!("\"{}\"", tab.group_title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 10_000;
+
+    fn policy(pattern: &str, protect: bool, collapsed_idle_time: Option<u64>, group_limit: Option<i32>) -> GroupPolicy {
+        GroupPolicy { pattern: pattern.to_string(), protect, collapsed_idle_time, group_limit }
+    }
+
+    fn tab(tab_inner_id: i32, group_id: i32, group_title: &str, group_collapsed: bool, tab_rss: i32, idle_time: u64) -> TabProcessInfo {
+        TabProcessInfo {
+            tab_inner_id,
+            chrome_tab_id: -1,
+            tab_process_id: 1000 + tab_inner_id,
+            tab_rss,
+            group_id,
+            group_title: group_title.to_string(),
+            group_collapsed,
+            last_used: NOW - idle_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn idle_tabs_of_a_collapsed_group_are_reclaimed() {
+        let mut manager = GroupManager::new(&GroupConfig { policy: vec![policy("Research", false, Some(600), None)] });
+        let tabs = [
+            tab(1, 5, "Research", true, 100, 900),
+            tab(2, 5, "Research", true, 100, 60),    // not idle long enough
+            tab(3, 6, "Research", false, 100, 900),  // expanded
+            tab(4, -1, "", false, 100, 900),         // outside a group
+        ];
+        let victims = manager.check(&tabs, |_| true, NOW);
+        assert_eq!(victims.len(), 1);
+        assert_eq!(victims[0].tab.tab_inner_id, 1);
+        assert!(!victims[0].under_pressure);
+        assert_eq!(manager.tab_group_violation[&1], "collapsed group \"Research\" idle for 900s");
+        assert_eq!(manager.tab_group_violation.len(), 1);
+    }
+
+    #[test]
+    fn unreclaimable_tab_is_recorded_but_not_picked() {
+        let mut manager = GroupManager::new(&GroupConfig { policy: vec![policy("*", false, Some(600), None)] });
+        let tabs = [tab(1, 5, "", true, 100, 900)];
+        assert!(manager.check(&tabs, |_| false, NOW).is_empty());
+        assert_eq!(manager.tab_group_violation[&1], "collapsed group #5 idle for 900s");
+    }
+
+    #[test]
+    fn first_matching_policy_protects_the_group() {
+        let mut manager = GroupManager::new(&GroupConfig {
+            policy: vec![policy("On call", true, None, None), policy("*", false, Some(0), None)],
+        });
+        let tabs = [tab(1, 5, "On call", true, 100, 900), tab(2, 6, "Other", true, 100, 900)];
+        assert!(manager.is_protected(&tabs[0]));
+        let victims = manager.check(&tabs, |_| true, NOW);
+        assert_eq!(victims.len(), 1);
+        assert_eq!(victims[0].tab.tab_inner_id, 2);
+    }
+
+    #[test]
+    fn group_over_its_limit_releases_its_biggest_tabs() {
+        let mut manager = GroupManager::new(&GroupConfig { policy: vec![policy("*", false, Some(600), Some(1000))] });
+        let tabs = [
+            tab(1, 5, "Work", false, 300, 0),
+            tab(2, 5, "Work", false, 700, 0),
+            tab(3, 5, "Work", false, 500, 0),
+        ];
+        // 1500 KB against 1000 KB: the 700 KB tab is enough
+        let victims = manager.check(&tabs, |_| true, NOW);
+        assert_eq!(victims.len(), 1);
+        assert_eq!(victims[0].tab.tab_inner_id, 2);
+        assert!(victims[0].under_pressure);
+        assert_eq!(manager.tab_group_violation.len(), 3);
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::{HashMap, VecDeque};
+use std::process::Command;
+use std::thread;
+use log::{error, info, warn};
+
+use crate::config_manager::GuardrailConfig;
+use crate::tab_manager::{TabProcessInfo, Visibility};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 3600;
+
+/// Global limits on top of every strategy, so a misconfigured strategy cannot wipe out the browser.
+///
+/// Releasing a renderer (discard or kill) is rate limited and never leaves fewer than
+/// `min_live_tabs` renderers, and no tab is reclaimed shortly after it was last active.
+/// When more distinct tabs than `breaker_kill_rate` are asked to be released within a minute
+/// the circuit breaker opens and halts reclamation until `breaker_cooldown` has passed.
+pub struct GuardrailManager {
+    pub config: GuardrailConfig,
+    pub kill_times: VecDeque<u64>,              // elapsed time of each release over the last hour
+    pub release_requests: VecDeque<(u64, i32)>, // (elapsed time, tab_inner_id) over the last minute
+    pub tab_last_active: HashMap<i32, u64>,     // tab_inner_id -> elapsed time, first seen if never active
+    pub live_tabs: usize,
+    pub breaker_opened_at: Option<u64>,
+    pub blocked: u64, // reclaim actions refused since start
+}
+
+impl GuardrailManager {
+    pub fn new(config: &GuardrailConfig) -> Self {
+        Self {
+            config: config.clone(),
+            kill_times: VecDeque::new(),
+            release_requests: VecDeque::new(),
+            tab_last_active: HashMap::new(),
+            live_tabs: 0,
+            breaker_opened_at: None,
+            blocked: 0,
+        }
+    }
+
+    /// Refreshes the windows and the last activity of each tab at the start of a tick.
+    pub fn update(&mut self, tabs: &[TabProcessInfo], live_tabs: usize, elapsed_time: u64) {
+        while self.kill_times.front().is_some_and(|&time| time + HOUR <= elapsed_time) {
+            self.kill_times.pop_front();
+        }
+        while self.release_requests.front().is_some_and(|&(time, _)| time + MINUTE <= elapsed_time) {
+            self.release_requests.pop_front();
+        }
+        self.tab_last_active.retain(|tab_inner_id, _| tabs.iter().any(|tab| tab.tab_inner_id == *tab_inner_id));
+        for tab in tabs.iter() {
+            let last_active = self.tab_last_active.entry(tab.tab_inner_id).or_insert(elapsed_time);
+            if tab.visibility == Visibility::Foreground || tab.in_use {
+                *last_active = elapsed_time;
+            }
+        }
+        self.live_tabs = live_tabs;
+
+        if let Some(opened_at) = self.breaker_opened_at {
+            if self.config.breaker_cooldown > 0 && elapsed_time >= opened_at + self.config.breaker_cooldown {
+                info!("Circuit breaker closed after {} seconds, resuming reclamation", elapsed_time - opened_at);
+                self.breaker_opened_at = None;
+                self.release_requests.clear();
+            }
+        }
+    }
+
+    pub fn close_breaker(&mut self) {
+        if self.breaker_opened_at.take().is_some() {
+            info!("Circuit breaker closed by the user");
+        }
+        self.release_requests.clear();
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.breaker_opened_at.is_some()
+    }
+
+    /// Any reclaim action waits until the tab has been in the background for `min_inactive_time`.
+    pub fn check_reclaim(&mut self, tab_inner_id: i32, elapsed_time: u64) -> Result<(), String> {
+        if self.is_open() {
+            return Err("circuit breaker is open".to_string());
+        }
+        let last_active = self.tab_last_active.get(&tab_inner_id).copied().unwrap_or(elapsed_time);
+        let inactive = elapsed_time - last_active;
+        if inactive < self.config.min_inactive_time {
+            return self.refuse(format!("inactive for {} of {} seconds", inactive, self.config.min_inactive_time));
+        }
+        Ok(())
+    }
+
+    /// Checks the kill rate and the live tab floor before a renderer is released.
+    /// Every request counts toward the circuit breaker, granted or not.
+    pub fn check_release(&mut self, tab_inner_id: i32, elapsed_time: u64) -> Result<(), String> {
+        if !self.release_requests.iter().any(|&(_, id)| id == tab_inner_id) {
+            self.release_requests.push_back((elapsed_time, tab_inner_id));
+        }
+        if self.config.breaker_kill_rate > 0 && self.release_requests.len() > self.config.breaker_kill_rate {
+            self.open_breaker(elapsed_time);
+            return Err("circuit breaker is open".to_string());
+        }
+
+        let kills_last_minute = self.kills_within(MINUTE, elapsed_time);
+        if self.config.max_kills_per_minute > 0 && kills_last_minute >= self.config.max_kills_per_minute {
+            return self.refuse(format!("{} kills in the last minute", kills_last_minute));
+        }
+        if self.config.max_kills_per_hour > 0 && self.kill_times.len() >= self.config.max_kills_per_hour {
+            return self.refuse(format!("{} kills in the last hour", self.kill_times.len()));
+        }
+        if self.live_tabs <= self.config.min_live_tabs {
+            return self.refuse(format!("only {} live tabs left", self.live_tabs));
+        }
+        Ok(())
+    }
+
+    /// Records a renderer that has just been released.
+    pub fn record_release(&mut self, elapsed_time: u64) {
+        self.kill_times.push_back(elapsed_time);
+        self.live_tabs = self.live_tabs.saturating_sub(1);
+    }
+
+    pub fn status(&self, elapsed_time: u64) -> serde_json::Value {
+        serde_json::json!({
+            "kills_last_minute": self.kills_within(MINUTE, elapsed_time),
+            "kills_last_hour": self.kill_times.len(),
+            "live_tabs": self.live_tabs,
+            "blocked": self.blocked,
+            "breaker_open": self.is_open(),
+            "breaker_open_for": self.breaker_opened_at.map(|opened_at| elapsed_time - opened_at),
+        })
+    }
+
+    fn kills_within(&self, window: u64, elapsed_time: u64) -> usize {
+        self.kill_times.iter().filter(|&&time| time + window > elapsed_time).count()
+    }
+
+    fn refuse(&mut self, reason: String) -> Result<(), String> {
+        self.blocked += 1;
+        Err(reason)
+    }
+
+    fn open_breaker(&mut self, elapsed_time: u64) {
+        self.breaker_opened_at = Some(elapsed_time);
+        error!(
+            "Circuit breaker opened: {} tabs asked to be released within a minute, reclamation halted",
+            self.release_requests.len()
+        );
+        if !self.config.notify {
+            return;
+        }
+        let body = format!(
+            "{} tabs were about to be killed within a minute, check the strategy in manager.toml",
+            self.release_requests.len()
+        );
+        match Command::new("notify-send").arg("Chrome memory manager halted").arg(body).spawn() {
+            // reaped off the tick so the daemon does not collect zombies
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(err) => warn!("Failed to send desktop notification: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(config: GuardrailConfig) -> GuardrailManager {
+        GuardrailManager::new(&GuardrailConfig { notify: false, ..config })
+    }
+
+    fn background(tab_inner_id: i32) -> TabProcessInfo {
+        TabProcessInfo { tab_inner_id, tab_process_id: 1000 + tab_inner_id, ..Default::default() }
+    }
+
+    #[test]
+    fn recently_active_tab_is_not_reclaimed() {
+        let mut manager = manager(GuardrailConfig::default());
+        let mut tab = background(1);
+        tab.visibility = Visibility::Foreground;
+        manager.update(std::slice::from_ref(&tab), 10, 100);
+        manager.update(&[background(1)], 10, 130);
+        assert!(manager.check_reclaim(1, 130).is_err());
+        assert!(manager.check_reclaim(1, 160).is_ok());
+        assert_eq!(manager.blocked, 1);
+    }
+
+    #[test]
+    fn kills_are_rate_limited() {
+        let mut manager = manager(GuardrailConfig { max_kills_per_minute: 2, max_kills_per_hour: 3, ..Default::default() });
+        manager.update(&[], 10, 0);
+        for (tab_inner_id, time) in [(1, 0), (2, 10)] {
+            assert!(manager.check_release(tab_inner_id, time).is_ok());
+            manager.record_release(time);
+        }
+        assert!(manager.check_release(3, 20).is_err());
+        // a minute later the minute limit is lifted, the hour limit is not
+        manager.update(&[], 10, 70);
+        assert!(manager.check_release(3, 70).is_ok());
+        manager.record_release(70);
+        manager.update(&[], 10, 140);
+        assert!(manager.check_release(4, 140).is_err());
+        manager.update(&[], 10, 3600);
+        assert!(manager.check_release(4, 3600).is_ok());
+    }
+
+    #[test]
+    fn live_tab_floor() {
+        let mut manager = manager(GuardrailConfig { min_live_tabs: 3, ..Default::default() });
+        manager.update(&[], 4, 0);
+        assert!(manager.check_release(1, 0).is_ok());
+        manager.record_release(0);
+        assert!(manager.check_release(2, 0).is_err());
+    }
+
+    #[test]
+    fn breaker_opens_on_a_release_storm_and_closes_after_cooldown() {
+        let mut manager = manager(GuardrailConfig { breaker_kill_rate: 3, breaker_cooldown: 600, min_live_tabs: 0, ..Default::default() });
+        manager.update(&[], 100, 0);
+        for tab_inner_id in 1..=3 {
+            let _ = manager.check_release(tab_inner_id, 0);
+        }
+        // asking again for the same tab does not count twice
+        let _ = manager.check_release(3, 0);
+        assert!(!manager.is_open());
+        assert!(manager.check_release(4, 0).is_err());
+        assert!(manager.is_open());
+        assert!(manager.check_reclaim(5, 300).is_err());
+
+        manager.update(&[], 100, 599);
+        assert!(manager.is_open());
+        manager.update(&[], 100, 600);
+        assert!(!manager.is_open());
+        assert!(manager.release_requests.is_empty());
+    }
+
+    #[test]
+    fn user_closes_the_breaker() {
+        let mut manager = manager(GuardrailConfig { breaker_kill_rate: 1, breaker_cooldown: 0, ..Default::default() });
+        manager.update(&[], 100, 0);
+        let _ = manager.check_release(1, 0);
+        let _ = manager.check_release(2, 0);
+        manager.update(&[], 100, 100_000);
+        assert!(manager.is_open());
+        manager.close_breaker();
+        assert!(!manager.is_open());
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::audit_manager::AuditRecord;
+use crate::config_manager::HistoryConfig;
+use crate::tab_manager::TabProcessInfo;
+
+/// One sample of a tab, written as a JSON line into a segment file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryRecord {
+    pub timestamp: u64, // unix second
+    pub tab_id: i32,
+    pub tab_name: String,
+    pub url: String,
+    pub pid: i32,
+    pub rss: i32,
+    pub is_active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+/// Filter shared by the history store and the audit log, `strategy` only applies to the audit log.
+#[derive(Default)]
+pub struct HistoryFilter {
+    pub tab_id: Option<i32>,
+    pub url: Option<String>, // substring of the tab url
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub strategy: Option<String>,
+}
+
+/// Append-only per-tab history stored as segment files `segment-<start>.jsonl` in `path`.
+///
+/// The active segment is rotated once it grows over `segment_max_bytes`. On rotation, segments
+/// older than `downsample_after` are rewritten with one sample per tab per `downsample_interval`
+/// (samples with an action are always kept) and segments older than `retention` are deleted.
+pub struct HistoryManager {
+    pub path: PathBuf,
+    pub segment_max_bytes: u64,
+    pub downsample_after: u64,
+    pub downsample_interval: u64,
+    pub retention: u64,
+    current_segment: Option<(BufWriter<File>, u64)>, // writer, bytes written
+}
+
+impl HistoryManager {
+    pub fn new(config: &HistoryConfig) -> Self {
+        Self {
+            path: PathBuf::from(&config.path),
+            segment_max_bytes: config.segment_max_bytes,
+            downsample_after: config.downsample_after,
+            downsample_interval: config.downsample_interval,
+            retention: config.retention,
+            current_segment: None,
+        }
+    }
+
+    /// Appends one record per tab for this tick, `actions` are the tiers entered during the tick.
+    pub fn append(&mut self, tabs: &[TabProcessInfo], actions: &[AuditRecord]) -> io::Result<()> {
+        let timestamp = unix_now();
+        if self.current_segment.as_ref().is_none_or(|(_, size)| *size >= self.segment_max_bytes) {
+            self.rotate(timestamp)?;
+        }
+        let Some((writer, size)) = self.current_segment.as_mut() else {
+            return Ok(());
+        };
+
+        for tab in tabs.iter() {
+            let action = actions
+                .iter()
+                .find(|record| record.tab_id == tab.tab_inner_id)
+                .map(|record| format!("{}: {}", record.action, record.reason));
+            let record = HistoryRecord {
+                timestamp,
+                tab_id: tab.tab_inner_id,
+                tab_name: tab.tab_name.clone(),
+                url: tab.url.clone(),
+                pid: tab.tab_process_id,
+                rss: tab.tab_rss,
+                is_active: tab.is_active,
+                action,
+            };
+            let line = serde_json::to_string(&record)?;
+            writeln!(writer, "{}", line)?;
+            *size += line.len() as u64 + 1;
+        }
+        writer.flush()
+    }
+
+    /// Returns the records matching `filter` in time order.
+    pub fn query(&self, filter: &HistoryFilter) -> io::Result<Vec<HistoryRecord>> {
+        let mut records = Vec::new();
+        for (start, segment) in self.segments()? {
+            if filter.to.is_some_and(|to| start > to) {
+                break;
+            }
+            for line in BufReader::new(File::open(&segment)?).lines() {
+                // the last line of the active segment may be cut by a crash
+                let Ok(record) = serde_json::from_str::<HistoryRecord>(&line?) else {
+                    continue;
+                };
+                if filter.tab_id.is_some_and(|tab_id| record.tab_id != tab_id)
+                    || filter.url.as_ref().is_some_and(|url| !record.url.contains(url.as_str()))
+                    || filter.from.is_some_and(|from| record.timestamp < from)
+                    || filter.to.is_some_and(|to| record.timestamp > to)
+                {
+                    continue;
+                }
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    fn rotate(&mut self, timestamp: u64) -> io::Result<()> {
+        fs::create_dir_all(&self.path)?;
+        if let Some((mut writer, _)) = self.current_segment.take() {
+            writer.flush()?;
+        }
+        self.compact(timestamp)?;
+
+        let segment = self.path.join(format!("segment-{:020}.jsonl", timestamp));
+        let file = OpenOptions::new().create(true).append(true).open(segment)?;
+        let size = file.metadata()?.len();
+        self.current_segment = Some((BufWriter::new(file), size));
+        Ok(())
+    }
+
+    /// Downsamples old segments and drops the ones past retention.
+    fn compact(&self, now: u64) -> io::Result<()> {
+        let segments = self.segments()?;
+        // a segment ends where the next one starts
+        for (index, (_, segment)) in segments.iter().enumerate() {
+            let Some(&(end, _)) = segments.get(index + 1) else {
+                break;
+            };
+            if now.saturating_sub(end) > self.retention {
+                fs::remove_file(segment)?;
+            } else if now.saturating_sub(end) > self.downsample_after && !is_compacted(segment) {
+                self.downsample(segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn downsample(&self, segment: &Path) -> io::Result<()> {
+        let mut last_kept: HashMap<i32, u64> = HashMap::new();
+        let mut kept = Vec::new();
+        for line in BufReader::new(File::open(segment)?).lines() {
+            let line = line?;
+            let Ok(record) = serde_json::from_str::<HistoryRecord>(&line) else {
+                continue;
+            };
+            let due = last_kept
+                .get(&record.tab_id)
+                .is_none_or(|last| record.timestamp >= last + self.downsample_interval);
+            if due || record.action.is_some() {
+                last_kept.insert(record.tab_id, record.timestamp);
+                kept.push(line);
+            }
+        }
+
+        let compacted = segment.with_extension("compacted.jsonl");
+        let mut writer = BufWriter::new(File::create(&compacted)?);
+        for line in kept {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        fs::remove_file(segment)
+    }
+
+    /// Segment files sorted by their start timestamp.
+    fn segments(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut segments = Vec::new();
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(segments),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let start = name
+                .strip_prefix("segment-")
+                .and_then(|rest| rest.split('.').next())
+                .and_then(|start| start.parse::<u64>().ok());
+            if let Some(start) = start {
+                segments.push((start, path));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+}
+
+fn is_compacted(segment: &Path) -> bool {
+    segment.to_string_lossy().ends_with(".compacted.jsonl")
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+/// Parses a point in time given either as a unix timestamp or as an age such as "90s", "30m", "2h", "1d".
+pub fn parse_time(value: &str) -> Option<u64> {
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Some(timestamp);
+    }
+    let (index, unit) = value.char_indices().last()?;
+    let amount: u64 = value[..index].parse().ok()?;
+    let seconds = match unit {
+        's' => Some(amount),
+        'm' => amount.checked_mul(60),
+        'h' => amount.checked_mul(3600),
+        'd' => amount.checked_mul(86400),
+        _ => None,
+    }?;
+    Some(unix_now().saturating_sub(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_accepts_timestamps_and_ages() {
+        assert_eq!(parse_time("1700000000"), Some(1700000000));
+        let now = unix_now();
+        let age = |value: &str| parse_time(value).map(|time| now - time);
+        assert!(age("30s").is_some_and(|age| (30..32).contains(&age)));
+        assert!(age("2h").is_some_and(|age| (7200..7202).contains(&age)));
+        assert!(age("1d").is_some_and(|age| (86400..86402).contains(&age)));
+    }
+
+    #[test]
+    fn parse_time_rejects_malformed_values() {
+        for value in ["", "m", "5x", "5é", "é", "-5m", "5 m", "99999999999999999999d", "999999999999999d"] {
+            assert_eq!(parse_time(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::thread;
+use log::warn;
+
+use crate::channel_manager::ChannelSender;
+use crate::config_manager::LeakConfig;
+use crate::process_handle::ProcessKey;
+use crate::tab_manager::TabProcessInfo;
+use crate::text_width;
+use crate::time_series::TimeSeries;
+
+const LEAK_NOTIFICATION_TITLE: &str = "Chrome tab is leaking memory";
+
+/// A renderer whose memory kept growing over the whole horizon.
+#[derive(Clone)]
+pub struct LeakSuspect {
+    pub slope: f64, // KB per second
+    pub r_squared: f64,
+    pub growth: f64, // KB over the horizon
+}
+
+/// Flags tabs whose RSS grows steadily, active or not.
+///
+/// Each renderer keeps one sample per `sample_interval` over `horizon` seconds. A linear fit with
+/// a slope above `min_slope` and an R² above `min_r_squared` means the growth is monotonic rather
+/// than the usual up and down of a busy page.
+pub struct LeakManager {
+    pub config: LeakConfig,
+    pub process_rss_history: HashMap<ProcessKey, TimeSeries>,
+    pub leak_suspects: HashMap<i32, LeakSuspect>, // tab_inner_id -> suspect
+}
+
+impl LeakManager {
+    pub fn new(config: &LeakConfig) -> Self {
+        Self { config: config.clone(), process_rss_history: HashMap::new(), leak_suspects: HashMap::new() }
+    }
+
+    /// Records this tick and refreshes `leak_suspects`, returns the tabs flagged for the first time.
+    pub fn update(&mut self, tabs: &[TabProcessInfo], elapsed_time: u64) -> Vec<TabProcessInfo> {
+        let mut newly_flagged = Vec::new();
+        let mut leak_suspects = HashMap::new();
+
+        for tab in tabs.iter().filter(|tab| tab.tab_process_id != -1) {
+            let series = self
+                .process_rss_history
+                .entry(tab.process_key())
+                .or_insert_with(|| TimeSeries::new(self.config.horizon, 1.0));
+            let due = series
+                .samples
+                .back()
+                .is_none_or(|&(last, _)| elapsed_time >= last + self.config.sample_interval);
+            if due {
+                series.push(elapsed_time, tab.tab_rss);
+            }
+            if !series.is_full() {
+                continue;
+            }
+            let Some(stats) = series.stats() else {
+                continue;
+            };
+            if stats.slope < self.config.min_slope || stats.r_squared < self.config.min_r_squared {
+                continue;
+            }
+
+            let suspect = LeakSuspect {
+                slope: stats.slope,
+                r_squared: stats.r_squared,
+                growth: stats.slope * self.config.horizon as f64,
+            };
+            if !self.leak_suspects.contains_key(&tab.tab_inner_id) {
+                newly_flagged.push(tab.clone());
+            }
+            leak_suspects.insert(tab.tab_inner_id, suspect);
+        }
+
+        self.leak_suspects = leak_suspects;
+        newly_flagged
+    }
+
+    /// Shown by the extension when connected, through `notify-send` otherwise; failures are only logged.
+    pub fn notify(&self, tab: &TabProcessInfo, channel: &ChannelSender) {
+        if !self.config.notify {
+            return;
+        }
+        let Some(suspect) = self.leak_suspects.get(&tab.tab_inner_id) else {
+            return;
+        };
+        let body = format!(
+            "{} grew {:.0} MB in the last {} minutes",
+            text_width::truncate(&tab.tab_name, text_width::TITLE_WIDTH),
+            suspect.growth / 1024.0,
+            self.config.horizon / 60
+        );
+        if channel.notify(LEAK_NOTIFICATION_TITLE, &body) {
+            return;
+        }
+        match Command::new("notify-send").arg(LEAK_NOTIFICATION_TITLE).arg(body).spawn() {
+            // reaped off the tick, every notification would leave a zombie behind otherwise
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(err) => warn!("Failed to send desktop notification: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> LeakManager {
+        LeakManager::new(&LeakConfig { horizon: 600, sample_interval: 30, notify: false, ..LeakConfig::default() })
+    }
+
+    fn tab(tab_inner_id: i32, tab_rss: i32) -> TabProcessInfo {
+        TabProcessInfo { tab_inner_id, tab_process_id: 1000 + tab_inner_id, tab_rss, ..Default::default() }
+    }
+
+    /// Feeds one tick every sample interval over the horizon, returns the tabs flagged on each tick.
+    fn run(manager: &mut LeakManager, rss: impl Fn(i32, u64) -> i32) -> Vec<Vec<i32>> {
+        (0..=25)
+            .map(|tick| {
+                let elapsed_time = tick * 30;
+                let tabs = [tab(1, rss(1, elapsed_time)), tab(2, rss(2, elapsed_time))];
+                manager.update(&tabs, elapsed_time).iter().map(|tab| tab.tab_inner_id).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steady_growth_is_flagged_once() {
+        let mut manager = manager();
+        // tab 1 grows 20 KB per second, tab 2 stays flat
+        let flagged = run(&mut manager, |tab_inner_id, time| if tab_inner_id == 1 { 100_000 + 20 * time as i32 } else { 100_000 });
+        assert_eq!(flagged.concat(), vec![1]);
+        // nothing is flagged before a whole horizon is covered
+        assert!(flagged[..20].iter().all(Vec::is_empty));
+        let suspect = &manager.leak_suspects[&1];
+        assert!((suspect.slope - 20.0).abs() < 1e-6);
+        assert!((suspect.growth - 12_000.0).abs() < 1e-3);
+        assert!(!manager.leak_suspects.contains_key(&2));
+    }
+
+    #[test]
+    fn noisy_growth_is_not_flagged() {
+        let mut manager = manager();
+        // the same trend buried under swings of a busy page
+        let flagged = run(&mut manager, |_, time| {
+            let swing = if (time / 30) % 2 == 0 { 50_000 } else { -50_000 };
+            100_000 + 20 * time as i32 + swing
+        });
+        assert!(flagged.concat().is_empty());
+        assert!(manager.leak_suspects.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config_manager::LogConfig;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Human,
+    Json,
+    Journald,
+}
+
+/// Logger behind the `log` macros: a global level, per-module levels and one output format.
+pub struct LogManager {
+    pub level: LevelFilter,
+    pub module_levels: Vec<(String, LevelFilter)>, // module name, e.g. "memory_manager"
+    pub format: LogFormat,
+    journald: Option<UnixDatagram>,
+}
+
+impl LogManager {
+    /// Installs the logger. With format "auto" the output goes to journald when the process runs
+    /// under systemd (stderr connected to the journal), to the human format otherwise.
+    pub fn init(config: &LogConfig) {
+        let level = LevelFilter::from_str(&config.level).unwrap_or_else(|_| panic!("Invalid log level: {}", config.level));
+        let module_levels: Vec<(String, LevelFilter)> = config
+            .modules
+            .iter()
+            .map(|(module, level)| {
+                let level = LevelFilter::from_str(level).unwrap_or_else(|_| panic!("Invalid log level for {}: {}", module, level));
+                (module.clone(), level)
+            })
+            .collect();
+
+        let mut format = match config.format.as_str() {
+            "human" => LogFormat::Human,
+            "json" => LogFormat::Json,
+            "journald" => LogFormat::Journald,
+            "auto" if std::env::var_os("JOURNAL_STREAM").is_some() => LogFormat::Journald,
+            "auto" => LogFormat::Human,
+            format => panic!("Invalid log format: {}", format),
+        };
+        let journald = match format {
+            LogFormat::Journald => UnixDatagram::unbound()
+                .and_then(|socket| socket.connect(JOURNALD_SOCKET).map(|_| socket))
+                .ok(),
+            _ => None,
+        };
+        if format == LogFormat::Journald && journald.is_none() {
+            format = LogFormat::Human;
+        }
+
+        let max_level = module_levels.iter().map(|(_, level)| *level).fold(level, Ord::max);
+        let logger = LogManager { level, module_levels, format, journald };
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(max_level);
+        }
+    }
+
+    /// The most specific module filter wins, "memory_manager" matches the target
+    /// "memory_management_for_chrome::memory_manager".
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let module = target.split_once("::").map_or(target, |(_, module)| module);
+        self.module_levels
+            .iter()
+            .filter(|(name, _)| module == name || module.starts_with(&format!("{}::", name)))
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    fn write_journald(&self, socket: &UnixDatagram, record: &Record) {
+        let priority = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        let mut datagram = Vec::new();
+        append_journald_field(&mut datagram, "MESSAGE", &record.args().to_string());
+        append_journald_field(&mut datagram, "PRIORITY", &priority.to_string());
+        append_journald_field(&mut datagram, "SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME"));
+        append_journald_field(&mut datagram, "TARGET", record.target());
+        if let Some(file) = record.file() {
+            append_journald_field(&mut datagram, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            append_journald_field(&mut datagram, "CODE_LINE", &line.to_string());
+        }
+        let _ = socket.send(&datagram);
+    }
+}
+
+impl Log for LogManager {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record.target().split_once("::").map_or(record.target(), |(_, module)| module);
+        match (self.format, self.journald.as_ref()) {
+            (LogFormat::Journald, Some(socket)) => self.write_journald(socket, record),
+            (LogFormat::Json, _) => {
+                let line = serde_json::json!({
+                    "timestamp": format_timestamp(SystemTime::now()),
+                    "level": record.level().as_str(),
+                    "module": module,
+                    "message": record.args().to_string(),
+                });
+                let _ = writeln!(std::io::stderr(), "{}", line);
+            }
+            _ => {
+                let _ = writeln!(
+                    std::io::stderr(),
+                    "{} {:<5} {}: {}",
+                    format_timestamp(SystemTime::now()),
+                    record.level(),
+                    module,
+                    record.args()
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Native journald protocol: `KEY=value\n`, or `KEY\n<u64 le length><value>\n` for multi-line values.
+fn append_journald_field(datagram: &mut Vec<u8>, key: &str, value: &str) {
+    datagram.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
+
+/// Formats a time as UTC "YYYY-MM-DDTHH:MM:SSZ".
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+    // civil date from days since 1970-01-01, Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
@@ -1,61 +1,453 @@
+#![allow(non_snake_case)]
+
 mod memory_manager;
 mod tab_manager;
 mod server_manager;
 mod config_manager;
+mod escalation_manager;
+mod budget_manager;
+mod time_series;
+mod history_manager;
+mod leak_manager;
+mod audit_manager;
+mod log_manager;
+mod systemd_manager;
+mod readiness_manager;
+mod manager_error;
+mod guardrail_manager;
+mod process_handle;
+mod verification_manager;
+mod control_manager;
+mod tui_manager;
+mod text_width;
+mod reuse_manager;
+mod pattern_manager;
+mod group_manager;
+mod channel_manager;
 
 use memory_manager::MemoryManager;
 use tab_manager::TabManager;
 use server_manager::ServerManager;
 use config_manager::ConfigManager;
+use audit_manager::AuditManager;
+use history_manager::{HistoryFilter, HistoryManager};
+use log_manager::LogManager;
+use systemd_manager::SystemdManager;
+use readiness_manager::ReadinessManager;
+use control_manager::{ControlContext, ControlManager};
+use channel_manager::ChannelManager;
+use tui_manager::TuiManager;
+use log::{error, info, warn};
 
 use std::path::Path;
 use std::thread;
 use std::time::Duration;
 
 
+const TAB_INFO_SERVER_ADDRESS: &str = "127.0.0.1:8080";
+
+const USAGE: &str = "\
+Usage: memory_management_for_chrome [COMMAND]
+
+Commands:
+  (none)    run the memory manager
+  query     print the tab history: query [--tab ID] [--url TEXT] [--from TIME] [--to TIME]
+  history   print the reclaim audit log: history [--tab ID] [--url TEXT] [--strategy NAME] [--from TIME] [--to TIME]
+            TIME is a unix timestamp or an age such as 30m, 2h, 1d
+  unit      print the systemd user units, unit --install writes them to ~/.config/systemd/user
+  host      print the native messaging host manifest for the extension: host [--install] [EXTENSION_ID]
+            --install writes it to ~/.config/google-chrome/NativeMessagingHosts, the extension id
+            defaults to [channel] extension_id
+  ctl       control the running manager:
+            ctl tabs | status | pause | resume | audit [N] | strategy NAME
+            ctl kill ID | discard ID | freeze ID | reload ID | protect ID | unprotect ID
+  top       interactive view of the running manager
+  evaluate  replay the tab history with the rss_limit strategy and compare the reloads of
+            each victim selection policy: evaluate [--tab ID] [--url TEXT] [--from TIME] [--to TIME]";
+
 fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = Path::new("manager.toml");
+    match args.first().map(String::as_str) {
+        None => run_manager(config_path, None),
+        // Chrome starts a native messaging host with the origin of the extension
+        Some(origin) if origin.starts_with("chrome-extension://") => run_manager(config_path, Some(origin)),
+        Some("query") => query_command(&args[1..], config_path),
+        Some("history") => history_command(&args[1..], config_path),
+        Some("unit") => unit_command(&args[1..], config_path),
+        Some("host") => host_command(&args[1..], config_path),
+        Some("ctl") => ctl_command(&args[1..], config_path),
+        Some("top") => top_command(config_path),
+        Some("evaluate") => evaluate_command(&args[1..], config_path),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Parses the filter flags shared by the query and history commands.
+fn parse_filter(args: &[String], allow_strategy: bool) -> HistoryFilter {
+    let mut filter = HistoryFilter::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().map(String::as_str).unwrap_or_else(|| {
+            eprintln!("Missing value for {}\n{}", flag, USAGE);
+            std::process::exit(2);
+        });
+        let valid = match flag.as_str() {
+            "--tab" => {
+                filter.tab_id = value.parse().ok();
+                filter.tab_id.is_some()
+            }
+            "--url" => {
+                filter.url = Some(value.to_string());
+                true
+            }
+            "--from" => {
+                filter.from = history_manager::parse_time(value);
+                filter.from.is_some()
+            }
+            "--to" => {
+                filter.to = history_manager::parse_time(value);
+                filter.to.is_some()
+            }
+            "--strategy" if allow_strategy => {
+                filter.strategy = Some(value.to_string());
+                true
+            }
+            _ => false,
+        };
+        if !valid {
+            eprintln!("Invalid argument: {} {}\n{}", flag, value, USAGE);
+            std::process::exit(2);
+        }
+    }
+    filter
+}
+
+/// Prints the records of the history store matching the command line filters.
+fn query_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let config_manager = ConfigManager::new(config_path);
+    let history_manager = HistoryManager::new(&config_manager.history);
+    let filter = parse_filter(args, false);
+
+    for record in history_manager.query(&filter)? {
+        println!(
+            "{} tab_id: {:<5} pid: {:>7} rss: {:>8} KB is_active: {:>5} url: {} {}",
+            record.timestamp,
+            record.tab_id,
+            record.pid,
+            record.rss,
+            record.is_active,
+            record.url,
+            record.action.map(|action| format!("action: {}", action)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Prints the reclaim actions of the audit log matching the command line filters.
+fn history_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let config_manager = ConfigManager::new(config_path);
+    let audit_manager = AuditManager::new(&config_manager.audit);
+    let filter = parse_filter(args, true);
+
+    for record in audit_manager.query(&filter)? {
+        println!(
+            "{} tab_id: {:<5} pid: {:>7} rss_before: {:>8} KB strategy: {:<18} action: {:<7} success: {:>5} title: {} url: {} reason: {}{}",
+            record.timestamp,
+            record.tab_id,
+            record.pid,
+            record.rss_before,
+            record.strategy,
+            record.action,
+            record.success,
+            text_width::truncate(&record.title, text_width::TITLE_WIDTH),
+            record.url,
+            record.reason,
+            record.error.map(|error| format!(" error: {}", error)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Generates the systemd user service and socket units.
+fn unit_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let install = match args {
+        [] => false,
+        [flag] if flag == "--install" => true,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config_manager = ConfigManager::new(config_path);
+    for path in systemd_manager::generate_units(install, config_manager.reflush_time)? {
+        println!("Wrote {}", path.display());
+    }
+    if install {
+        println!(
+            "Run: systemctl --user daemon-reload && systemctl --user enable --now {0}-ingestion.socket {0}-metrics.socket {0}.service",
+            systemd_manager::SERVICE_NAME
+        );
+    }
+    Ok(())
+}
+
+/// Generates the native messaging host manifest that lets the extension start the manager.
+fn host_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let install = args.iter().any(|arg| arg == "--install");
+    let extension_id = match args.iter().filter(|arg| *arg != "--install").collect::<Vec<_>>().as_slice() {
+        [] => ConfigManager::new(config_path).channel.extension_id,
+        [extension_id] => extension_id.to_string(),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    // Chrome only accepts exact origins, an extension id is 32 letters between a and p
+    if extension_id.len() != 32 || !extension_id.chars().all(|c| ('a'..='p').contains(&c)) {
+        eprintln!("A valid extension id is required, pass it or set [channel] extension_id (see chrome://extensions)");
+        std::process::exit(2);
+    }
+    for path in channel_manager::generate_host_manifest(install, &extension_id)? {
+        println!("Wrote {}", path.display());
+    }
+    if install {
+        println!("Reload the extension, it starts the manager through {}", channel_manager::HOST_NAME);
+    }
+    Ok(())
+}
+
+/// Sends one request to the control API of the running manager and prints the result.
+fn ctl_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let usage = || -> ! {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    let tab_id = |value: &String| value.parse::<i32>().unwrap_or_else(|_| usage());
+    let (method, params) = match args {
+        [command] if command == "tabs" => ("list_tabs", serde_json::json!({})),
+        [command] if command == "status" || command == "pause" || command == "resume" => (command.as_str(), serde_json::json!({})),
+        [command] if command == "audit" => ("audit", serde_json::json!({})),
+        [command, limit] if command == "audit" => ("audit", serde_json::json!({ "limit": limit.parse::<u64>().unwrap_or_else(|_| usage()) })),
+        [command, strategy] if command == "strategy" => ("set_strategy", serde_json::json!({ "strategy": strategy })),
+        [command, id] if ["kill", "discard", "freeze", "reload", "protect", "unprotect"].contains(&command.as_str()) => {
+            (command.as_str(), serde_json::json!({ "tab_id": tab_id(id) }))
+        }
+        _ => usage(),
+    };
+
+    let config_manager = ConfigManager::new(config_path);
+    let socket_path = ControlManager::socket_path(&config_manager.control);
+    let result = control_manager::call(&socket_path, method, params).unwrap_or_else(|err| {
+        eprintln!("Cannot reach the manager on {}: {}", socket_path.display(), err);
+        std::process::exit(1);
+    });
+    match result {
+        Ok(serde_json::Value::Array(tabs)) if method == "list_tabs" => {
+            for tab in tabs.iter() {
+                println!(
+                    "tab_id: {:<5} pid: {:>7} rss: {:>8} KB tier: {:<7} active: {:>5} protected: {:>5} title: {}",
+                    tab["tab_id"], tab["pid"], tab["rss"], tab["tier"].as_str().unwrap_or_default(),
+                    tab["is_active"], tab["protected"], text_width::truncate(tab["title"].as_str().unwrap_or_default(), text_width::TITLE_WIDTH)
+                );
+            }
+        }
+        Ok(result) => println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default()),
+        Err(err) => {
+            eprintln!("Error {}: {}", err.code, err.message);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Opens the interactive view on the control API of the running manager.
+fn top_command(config_path: &Path) -> std::io::Result<()> {
+    let config_manager = ConfigManager::new(config_path);
+    let socket_path = ControlManager::socket_path(&config_manager.control);
+    TuiManager::new(&socket_path).run().inspect_err(|err| {
+        eprintln!("Cannot reach the manager on {}: {}", socket_path.display(), err);
+    })
+}
+
+/// Replays the recorded history to show how many reloads the activation patterns save.
+fn evaluate_command(args: &[String], config_path: &Path) -> std::io::Result<()> {
+    let config_manager = ConfigManager::new(config_path);
+    let history_manager = HistoryManager::new(&config_manager.history);
+    let records = history_manager.query(&parse_filter(args, false))?;
+    let (activations, evaluations) = pattern_manager::evaluate(
+        &records,
+        &config_manager.reuse,
+        &config_manager.pattern,
+        config_manager.rss_high_watermark,
+        config_manager.rss_low_watermark,
+    );
+    println!("{} records, {} activations", records.len(), activations);
+    for evaluation in evaluations {
+        let reload_rate = if evaluation.reclaims == 0 { 0.0 } else { evaluation.reloads as f64 / evaluation.reclaims as f64 };
+        println!(
+            "policy: {:<8} reclaims: {:>6} reloads: {:>6} reload rate: {:>5.1}%",
+            evaluation.policy,
+            evaluation.reclaims,
+            evaluation.reloads,
+            reload_rate * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// Runs the manager, `native_origin` is the extension that started it as a native messaging host.
+fn run_manager(config_path: &Path, native_origin: Option<&str>) -> std::io::Result<()> {
     let chrome_info_path = Path::new("/proc/chrome_info");
     let log_path = Path::new("log.json");
-    let config_path = Path::new("manager.toml");
     let mut manager = TabManager::new();
+    let systemd_manager = SystemdManager::new();
     let config_manager = ConfigManager::new(config_path);
-    let mut memory_manager = MemoryManager::new(config_manager.rss_limit, config_manager.idel_time_limit,config_manager.memory_change_rate);
+    LogManager::init(&config_manager.log);
+    let mut memory_manager = MemoryManager::new(&config_manager);
+    let mut history_manager = HistoryManager::new(&config_manager.history);
+    let audit_manager = AuditManager::new(&config_manager.audit);
     let server_manager = ServerManager::new();
+    let mut strategy = config_manager.strategy.clone();
+    let mut tabs = Vec::new(); // tabs of the last successful tick, for the control API
+    let control_manager = if config_manager.control.enabled {
+        match ControlManager::start(&config_manager.control) {
+            Ok(control_manager) => Some(control_manager),
+            // a host started by Chrome next to a running manager would reclaim the same tabs
+            Err(e) if native_origin.is_some() && e.kind() == std::io::ErrorKind::AddrInUse => {
+                error!("Not starting the native messaging host: {}", e);
+                return Err(e);
+            }
+            Err(e) => {
+                error!("Control API disabled: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
     
+    // the extension channel takes the ingestion socket of the tab info server
+    let channel_manager = match native_origin {
+        Some(origin) => {
+            info!("Started by {} as its native messaging host", origin);
+            Some(ChannelManager::start_native(server_manager.stop_signal.clone())?)
+        }
+        None if config_manager.channel.enabled => {
+            ChannelManager::start(&config_manager.channel, systemd_manager.listen_fds.get("ingestion").copied())
+                .inspect_err(|e| error!("Extension channel disabled: {}", e))
+                .ok()
+        }
+        None => None,
+    };
+    if let Some(channel_manager) = channel_manager.as_ref() {
+        memory_manager.channel = channel_manager.sender.clone();
+    }
+
     server_manager.set_panic_hook();
     server_manager.set_signal_hook_handler();
-    server_manager.run_server_thread();
+    // a native messaging host opens no port at all
+    server_manager.run_server_thread(&systemd_manager.listen_fds, channel_manager.is_none(), native_origin.is_none())?;
 
-    println!("\x1b[42mWaiting for servers to start..., using strategy: {}\x1b[0m", config_manager.strategy);
-    thread::sleep(Duration::from_secs(15));
+    info!("Waiting for servers to start..., using strategy: {}", config_manager.strategy);
+    systemd_manager.status("Waiting for the tab info server, the tab snapshot and the process source");
+    let mut readiness_manager = match channel_manager.as_ref() {
+        Some(channel_manager) => ReadinessManager::with_channel(channel_manager, chrome_info_path.to_path_buf()),
+        None => ReadinessManager::new(TAB_INFO_SERVER_ADDRESS, log_path.to_path_buf(), chrome_info_path.to_path_buf(), config_manager.snapshot_max_age),
+    };
+    readiness_manager.wait(Duration::from_secs(config_manager.startup_timeout));
+    let mut readiness_status = readiness_manager.status();
+    info!("Startup: {}", readiness_status);
+    systemd_manager.ready(&format!("strategy: {}, {}", config_manager.strategy, readiness_status));
 
     while !*server_manager.stop_signal.lock().unwrap() {
-        manager.build_tab_info_map(log_path)?;
-        manager.get_pid_from_chrome_info(chrome_info_path)?;
-        manager.build_tab_process_info_map();
-        manager.build_tabid_tabname_tabpid_isActive_map();
-        manager.print_tab_process_info_map();
-
-        if let Err(err) = memory_manager.memory_killer(&manager.tabid_tabname_tabpid_isActive_map, config_manager.reflush_time, &config_manager.strategy) {
-            eprintln!("Failed to enforce memory limit: {}", err);
+        let all_ready = readiness_manager.refresh();
+        if readiness_manager.status() != readiness_status {
+            readiness_status = readiness_manager.status();
+            info!("Inputs changed: {}", readiness_status);
+            systemd_manager.status(&format!("strategy: {}, {}", strategy, readiness_status));
         }
 
-        if let Err(e) = manager.write_tab_process_info_to_file("output.json", &memory_manager.pid_inActive_time_counter) {
-            eprintln!("Failed to write to file: {}", e);
+        // tab events are applied as they come, whether or not the tick observes
+        if let Some(channel_manager) = channel_manager.as_ref() {
+            for event in channel_manager.events.try_iter() {
+                if let Err(e) = manager.apply_event(&event) {
+                    warn!("Ignoring channel event: {}", e);
+                }
+            }
         }
 
-        manager.tab_info_map.clear();
-        manager.pid_rss_vector.clear();
-        manager.tab_process_info_map.clear();
-        manager.tabid_tabname_tabpid_isActive_map.clear();
+        if readiness_manager.can_observe() {
+            let was_paused = memory_manager.reclaim_paused;
+            memory_manager.tick_actions.clear();
+            let mut tick = manager.observe(channel_manager.is_none().then_some(log_path), chrome_info_path);
+
+            if tick.is_ok() {
+                // reclaim only with every input available, a stale snapshot may point at the wrong tab
+                if all_ready {
+                    tick = memory_manager.memory_killer(&manager.tabid_tabname_tabpid_isActive_map, manager.browser_rss(), config_manager.reflush_time, &strategy);
 
-        thread::sleep(Duration::from_secs(config_manager.reflush_time));
-        
+                    if let Err(e) = audit_manager.append(&memory_manager.tick_actions) {
+                        error!("Failed to write audit log: {}", e);
+                    }
+                    if let Err(e) = memory_manager.pattern_manager.save_if_due(history_manager::unix_now()) {
+                        error!("Failed to save activation patterns: {}", e);
+                    }
+                }
+
+                if config_manager.history.enabled {
+                    if let Err(e) = history_manager.append(&manager.tabid_tabname_tabpid_isActive_map, &memory_manager.tick_actions) {
+                        error!("Failed to append to history: {}", e);
+                    }
+                }
+
+                if let Err(e) = manager.write_tab_process_info_to_file("output.json", &memory_manager) {
+                    error!("Failed to write to file: {}", e);
+                }
+            }
+
+            memory_manager.record_tick(&tick);
+            if memory_manager.reclaim_paused != was_paused {
+                let state = if memory_manager.reclaim_paused { "reclamation paused after failed ticks" } else { readiness_status.as_str() };
+                systemd_manager.status(&format!("strategy: {}, {}", strategy, state));
+            }
+
+            manager.pid_rss_vector.clear();
+            manager.tab_process_info_map.clear();
+            manager.pid_start_time_map.clear();
+            if tick.is_ok() {
+                tabs = std::mem::take(&mut manager.tabid_tabname_tabpid_isActive_map);
+            }
+            manager.tabid_tabname_tabpid_isActive_map.clear();
+        }
+
+        systemd_manager.watchdog();
+        let reflush_time = Duration::from_secs(config_manager.reflush_time);
+        match control_manager.as_ref() {
+            Some(control_manager) => control_manager.serve(reflush_time, ControlContext {
+                memory_manager: &mut memory_manager,
+                tabs: &tabs,
+                audit_manager: &audit_manager,
+                strategy: &mut strategy,
+            }),
+            None => thread::sleep(reflush_time),
+        }
     }
 
-    println!("Shutting down...");
+    info!("Shutting down...");
+    systemd_manager.stopping();
+    memory_manager.release_frozen_tabs();
+    if let Err(e) = memory_manager.pattern_manager.save(history_manager::unix_now()) {
+        error!("Failed to save activation patterns: {}", e);
+    }
+    if let Some(control_manager) = control_manager.as_ref() {
+        control_manager.cleanup();
+    }
     server_manager.cleanup_thread();
     server_manager.cleanup_temp_files();
-    println!("Cleanup completed.");
+    info!("Cleanup completed.");
     Ok(())
 }
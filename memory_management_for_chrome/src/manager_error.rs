@@ -0,0 +1,51 @@
+use nix::errno::Errno;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors of a single tick of `TabManager` and `MemoryManager`. None of them is fatal, the
+/// main loop reports them and skips the tick.
+#[derive(Debug)]
+pub enum ManagerError {
+    /// An input file is missing or cannot be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The tab snapshot is not valid JSON, usually because it is being rewritten.
+    Json { path: PathBuf, message: String },
+    /// A tab of the snapshot lacks a field or has the wrong type.
+    MissingField { tab_inner_id: i32, field: &'static str },
+    /// Sending a signal to (or paging out) a renderer failed.
+    Signal { pid: i32, source: Errno },
+    InvalidStrategy(String),
+}
+
+pub type Result<T> = std::result::Result<T, ManagerError>;
+
+impl ManagerError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        ManagerError::Io { path: path.into(), source }
+    }
+}
+
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::Io { path, source } => write!(f, "cannot read {}: {}", path.display(), source),
+            ManagerError::Json { path, message } => write!(f, "invalid JSON in {}: {}", path.display(), message),
+            ManagerError::MissingField { tab_inner_id, field } => {
+                write!(f, "tab {} has no valid \"{}\" field", tab_inner_id, field)
+            }
+            ManagerError::Signal { pid, source } => write!(f, "failed to act on process {}: {}", pid, source),
+            ManagerError::InvalidStrategy(strategy) => write!(f, "invalid strategy: {}", strategy),
+        }
+    }
+}
+
+impl std::error::Error for ManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManagerError::Io { source, .. } => Some(source),
+            ManagerError::Signal { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,142 +1,919 @@
 use nix::errno::Errno;
-use nix::Error;
-use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
-use std::collections::HashMap;
+use nix::sys::signal::Signal;
+use std::collections::{HashMap, HashSet};
+use log::{debug, error, info, warn};
+
+use crate::audit_manager::AuditRecord;
+use crate::budget_manager::BudgetManager;
+use crate::channel_manager::ChannelSender;
+use crate::config_manager::ConfigManager;
+use crate::escalation_manager::{EscalationManager, Tier};
+use crate::group_manager::GroupManager;
+use crate::guardrail_manager::GuardrailManager;
+use crate::leak_manager::LeakManager;
+use crate::manager_error::{self, ManagerError};
+use crate::history_manager;
+use crate::pattern_manager::PatternManager;
+use crate::process_handle::{ProcessHandle, ProcessKey};
+use crate::reuse_manager::ReuseManager;
+use crate::tab_manager::{TabProcessInfo, Visibility};
+use crate::text_width;
+use crate::time_series::TimeSeries;
+use crate::verification_manager::{TabKey, TrackedAction, VerificationManager};
+
+/// Strategies accepted by `memory_killer`.
+pub const STRATEGIES: [&str; 4] = ["idel_time_limit", "rss_limit", "memory_change_rate", "leak"];
 
 pub struct MemoryManager {
-    pub rss_limit: i32,
+    pub rss_high_watermark: i32,
+    pub rss_low_watermark: i32,
+    pub rss_reclaim_cooldown: u64,
+    pub rss_cooldown_remaining: u64,
+    pub rss_reclaiming: bool, // a reclaim episode runs from the high watermark down to the low one
+    pub rss_projection: Option<RssProjection>, // what the last reclaim pass expected to free
+    pub pageout_yield: f64, // share of its rss a paged out tab gives back, corrected after every pass
     pub idel_time_limit: i32,
     pub memory_change_rate: f32,
-    pub pid_inActive_time_counter: HashMap<i32, i32>,
-    pub pid_warmup_time_counter: HashMap<i32, u64>,
-    pub memory_total_using: HashMap<i32, u64>,
+    pub memory_change_window: u64,
+    pub memory_change_metric: String,
+    pub ewma_alpha: f64,
+    pub process_memory_series: HashMap<ProcessKey, TimeSeries>,
+    pub elapsed_time: u64, // second since start, advanced every tick
+    pub escalation_manager: EscalationManager,
+    pub budget_manager: BudgetManager,
+    pub group_manager: GroupManager,
+    pub leak_manager: LeakManager,
+    pub guardrail_manager: GuardrailManager,
+    pub verification_manager: VerificationManager,
+    pub reuse_manager: ReuseManager,
+    pub pattern_manager: PatternManager,
+    pub browser_rss: i32, // KB, every Chrome process at the current tick
+    pub escalated_tabs: HashSet<i32>, // tab_inner_id escalated during the current tick
+    pub tick_actions: Vec<AuditRecord>, // actions taken during the current tick
+    pub tick_error: Option<ManagerError>, // first failed action of the current tick
+    pub max_consecutive_failures: u32,
+    pub consecutive_failures: u32,
+    pub consecutive_successes: u32, // counted while paused, to resume
+    pub reclaim_paused: bool,
+    pub paused_by_user: bool,
+    pub protected_tabs: HashSet<i32>, // tab_inner_id never reclaimed, set through the control API
+    pub visible_max_tier: Option<Tier>,   // deepest tier of the active tab of another visible window, None protects it
+    pub minimized_max_tier: Option<Tier>, // deepest tier of the active tab of a minimized window
+    pub channel: ChannelSender, // commands to the extension, never connected without the channel
+}
+
+/// What a pass of the rss_limit strategy expected to free, checked on the next tick.
+pub struct RssProjection {
+    pub total: i32, // KB, total rss expected once the victims reached their tier
+    pub pageouts: Vec<(ProcessKey, i32)>, // paged out victims and their rss before the pageout
 }
 
 impl MemoryManager {
-    pub fn new(rss_limit: i32, idel_time_limit: i32, memory_change_rate: f32) -> Self {
-        Self { rss_limit, idel_time_limit, memory_change_rate, pid_inActive_time_counter: HashMap::new(), pid_warmup_time_counter: HashMap::new(), memory_total_using: HashMap::new()}
+    pub fn new(config_manager: &ConfigManager) -> Self {
+        Self {
+            rss_high_watermark: config_manager.rss_high_watermark,
+            rss_low_watermark: config_manager.rss_low_watermark,
+            rss_reclaim_cooldown: config_manager.rss_reclaim_cooldown,
+            rss_cooldown_remaining: 0,
+            rss_reclaiming: false,
+            rss_projection: None,
+            pageout_yield: config_manager.pageout_yield,
+            idel_time_limit: config_manager.idel_time_limit,
+            memory_change_rate: config_manager.memory_change_rate,
+            memory_change_window: config_manager.memory_change_window,
+            memory_change_metric: config_manager.memory_change_metric.clone(),
+            ewma_alpha: config_manager.ewma_alpha,
+            process_memory_series: HashMap::new(),
+            elapsed_time: 0,
+            escalation_manager: EscalationManager::new(&config_manager.escalation),
+            budget_manager: BudgetManager::new(&config_manager.budget),
+            group_manager: GroupManager::new(&config_manager.group),
+            leak_manager: LeakManager::new(&config_manager.leak),
+            guardrail_manager: GuardrailManager::new(&config_manager.guardrail),
+            verification_manager: VerificationManager::new(&config_manager.verification),
+            reuse_manager: ReuseManager::new(&config_manager.reuse),
+            pattern_manager: PatternManager::new(&config_manager.pattern),
+            browser_rss: 0,
+            escalated_tabs: HashSet::new(),
+            tick_actions: Vec::new(),
+            tick_error: None,
+            max_consecutive_failures: config_manager.max_consecutive_failures,
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            reclaim_paused: false,
+            paused_by_user: false,
+            protected_tabs: HashSet::new(),
+            visible_max_tier: Tier::parse(&config_manager.window.visible),
+            minimized_max_tier: Tier::parse(&config_manager.window.minimized),
+            channel: ChannelSender::default(),
+        }
     }
 
-    /// memory killer can use two strategy to kill the tab render process
+    /// Runs one tick: picks reclaim candidates with `strategy` (idel_time_limit, rss_limit,
+    /// memory_change_rate or leak) and the budgets and group policies, then moves each of them one
+    /// step up the escalation ladder.
     pub fn memory_killer(
         &mut self,
-        tabid_tabname_tabpid_isActive_map: &Vec<(i32, String, i32, i32, bool)>,
+        tabid_tabname_tabpid_isActive_map: &[TabProcessInfo],
+        browser_rss: i32,
         reflush_time: u64,
-        strategy: &String,
-    ) -> nix::Result<()> {
-        match strategy.as_str() {
-            "idel_time_limit" => self.kill_by_inActive_time(tabid_tabname_tabpid_isActive_map, reflush_time),
-            "rss_limit" => self.kill_by_exceed_rss_limit(tabid_tabname_tabpid_isActive_map),
-            "memory_change_rate" => self.kill_by_memory_change_rate(tabid_tabname_tabpid_isActive_map, self.memory_change_rate, reflush_time),
-            _ => {
-                eprintln!("Invalid strategy: {}", strategy);
-                return nix::Result::Err(Error::EFAULT);
+        strategy: &str,
+    ) -> manager_error::Result<()> {
+        self.escalated_tabs.clear();
+        self.tick_actions.clear();
+        self.tick_error = None;
+        self.elapsed_time += reflush_time;
+        self.browser_rss = browser_rss;
+        self.collect_garbage(tabid_tabname_tabpid_isActive_map);
+        let timestamp = history_manager::unix_now();
+        self.pattern_manager.observe(tabid_tabname_tabpid_isActive_map, timestamp);
+        self.reuse_manager.update(tabid_tabname_tabpid_isActive_map, self.elapsed_time, &self.pattern_manager, timestamp);
+        self.verification_manager.update(tabid_tabname_tabpid_isActive_map, browser_rss, self.elapsed_time);
+        self.confirm_discards(tabid_tabname_tabpid_isActive_map);
+        self.deescalate_active_tabs(tabid_tabname_tabpid_isActive_map)?;
+        self.detect_leaks(tabid_tabname_tabpid_isActive_map);
+        let live_tabs = tabid_tabname_tabpid_isActive_map
+            .iter()
+            .filter(|tab| tab.tab_process_id != -1 && !self.escalation_manager.tier_of(tab.tab_inner_id).is_released())
+            .count();
+        self.guardrail_manager.update(tabid_tabname_tabpid_isActive_map, live_tabs, self.elapsed_time);
+        // tabs the user comes back to are resumed even while the strategies are held back
+        if self.guardrail_manager.is_open() || !self.is_reclaiming() {
+            return Ok(());
+        }
+        match strategy {
+            "idel_time_limit" => self.kill_by_inActive_time(tabid_tabname_tabpid_isActive_map)?,
+            "rss_limit" => self.kill_by_exceed_rss_limit(tabid_tabname_tabpid_isActive_map, reflush_time)?,
+            "memory_change_rate" => self.kill_by_memory_change_rate(tabid_tabname_tabpid_isActive_map, self.memory_change_rate)?,
+            "leak" => self.kill_by_leak(tabid_tabname_tabpid_isActive_map)?,
+            _ => return Err(ManagerError::InvalidStrategy(strategy.to_string())),
+        }
+        self.enforce_budgets(tabid_tabname_tabpid_isActive_map)?;
+        self.enforce_groups(tabid_tabname_tabpid_isActive_map)?;
+        // the tick still fails, once every victim had its turn
+        self.tick_error.take().map_or(Ok(()), Err)
+    }
+
+    /// Counts failed ticks. Reclamation pauses after `max_consecutive_failures` of them in a row,
+    /// frozen tabs are resumed since nobody watches them anymore, and it resumes after as many
+    /// successful ticks in a row.
+    pub fn record_tick(&mut self, result: &manager_error::Result<()>) {
+        match result {
+            Ok(()) => {
+                self.consecutive_failures = 0;
+                if !self.reclaim_paused {
+                    return;
+                }
+                self.consecutive_successes += 1;
+                if self.consecutive_successes >= self.max_consecutive_failures {
+                    info!("Resuming reclamation after {} successful ticks", self.consecutive_successes);
+                    self.reclaim_paused = false;
+                    self.consecutive_successes = 0;
+                }
+            }
+            Err(err) => {
+                warn!("Skipping tick: {}", err);
+                self.consecutive_failures += 1;
+                self.consecutive_successes = 0;
+                if !self.reclaim_paused && self.consecutive_failures >= self.max_consecutive_failures {
+                    error!("Pausing reclamation after {} failed ticks in a row", self.consecutive_failures);
+                    self.reclaim_paused = true;
+                    self.release_frozen_tabs();
+                }
             }
         }
     }
 
-    fn kill_by_inActive_time(&mut self, tabid_tabname_tabpid_isActive_map: &Vec<(i32, String, i32, i32, bool)>, reflush_time: u64) -> nix::Result<()>{
-        for (tab_inner_id, _, tab_process_id, _, is_active) in tabid_tabname_tabpid_isActive_map.iter() {
-            if !is_active && tab_process_id != &-1{
-                let counter = self.pid_inActive_time_counter.entry(*tab_process_id).or_insert(0);
-                *counter += reflush_time as i32;
-                if *counter > self.idel_time_limit {
-                    kill_process(*tab_process_id).unwrap();
-                    println!(
-                        "Killing process with PID {} (Tab ID: {}) due to inactivity: {} seconds",
-                        tab_process_id, tab_inner_id, counter
-                    );
-                    self.pid_inActive_time_counter.remove(tab_process_id);
+    /// Strategies only run when neither repeated failures nor the user paused them.
+    pub fn is_reclaiming(&self) -> bool {
+        !self.reclaim_paused && !self.paused_by_user
+    }
+
+    /// Applies a tier chosen through the control API, whatever the strategy and the guardrails say.
+    pub fn manual_action(&mut self, tab: &TabProcessInfo, tier: Tier) -> AuditRecord {
+        info!("Moving process with PID {} (Tab ID: {}) to tier {} on request", tab.tab_process_id, tab.tab_inner_id, tier);
+        self.escalation_manager.set_tier(tab.process_key(), tier, self.elapsed_time);
+        let result = self.apply_tier(tab, tier);
+        if result.is_ok() {
+            if tier.is_released() {
+                self.guardrail_manager.record_release(self.elapsed_time);
+            }
+            self.verification_manager.track(TrackedAction {
+                process: tab.process_key(),
+                tab: TabKey::of(tab),
+                strategy: "manual".to_string(),
+                tier,
+                taken_at: self.elapsed_time,
+                tab_rss_before: tab.tab_rss,
+                browser_rss_before: self.browser_rss,
+            });
+        }
+        AuditRecord::new(tab, "manual", "control API", tier, &result)
+    }
+
+    /// Deepest tier a tab may be taken to given where it stands, None when it is never reclaimed.
+    pub fn max_tier(&self, tab: &TabProcessInfo) -> Option<Tier> {
+        match tab.visibility {
+            Visibility::Foreground => None,
+            Visibility::Visible => self.visible_max_tier,
+            Visibility::Minimized => self.minimized_max_tier,
+            Visibility::Background => Some(Tier::Kill),
+        }
+    }
+
+    /// Whether a strategy may pick the tab at all: not in the foreground, not released yet, not in
+    /// a protected group, and not an active tab whose window policy protects it or already holds it
+    /// at its deepest tier.
+    pub fn is_reclaimable(&self, tab: &TabProcessInfo) -> bool {
+        let tier = self.escalation_manager.tier_of(tab.tab_inner_id);
+        !tier.is_released()
+            && !self.group_manager.is_protected(tab)
+            && self.max_tier(tab).is_some_and(|max_tier| tier < max_tier)
+    }
+
+    /// Change rate of a tab over the last `memory_change_window`, once the window is full.
+    pub fn change_rate(&self, tab: &TabProcessInfo) -> Option<f64> {
+        let series = self.process_memory_series.get(&tab.process_key())?;
+        if !series.is_full() {
+            return None;
+        }
+        series.stats()?.change_rate(&self.memory_change_metric, self.memory_change_window)
+    }
+
+    /// Tabs the strategy, a budget or a group policy would pick on the next tick, guardrails and dwell times aside.
+    pub fn upcoming_victims(&self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo], strategy: &str) -> HashSet<i32> {
+        let timestamp = history_manager::unix_now();
+        let eligible = |tab: &&TabProcessInfo| {
+            self.is_reclaimable(tab)
+                && tab.tab_process_id != -1
+                && !self.protected_tabs.contains(&tab.tab_inner_id)
+                && !self.pattern_manager.is_expected_soon(tab, timestamp)
+        };
+        let candidates = tabid_tabname_tabpid_isActive_map.iter().filter(eligible);
+        let mut victims: HashSet<i32> = match strategy {
+            "idel_time_limit" => candidates
+                .filter(|tab| tab.idle_time(timestamp) > self.idel_time_limit as u64)
+                .map(|tab| tab.tab_inner_id)
+                .collect(),
+            "rss_limit" => {
+                let total_rss: i32 = tabid_tabname_tabpid_isActive_map.iter().map(|tab| tab.tab_rss).sum();
+                if self.rss_reclaiming || total_rss > self.rss_high_watermark {
+                    let unprotected: Vec<TabProcessInfo> = candidates.cloned().collect();
+                    let need = total_rss - self.rss_low_watermark;
+                    plan_rss_victims(&unprotected, |tab| self.is_reclaimable(tab), &self.reuse_manager, need, |tab| self.expected_release(tab))
+                        .iter()
+                        .map(|tab| tab.tab_inner_id)
+                        .collect()
+                } else {
+                    HashSet::new()
+                }
+            }
+            "memory_change_rate" => candidates
+                .filter(|tab| self.change_rate(tab).is_some_and(|rate| rate < self.memory_change_rate as f64))
+                .map(|tab| tab.tab_inner_id)
+                .collect(),
+            "leak" => candidates
+                .filter(|tab| self.leak_manager.leak_suspects.contains_key(&tab.tab_inner_id))
+                .map(|tab| tab.tab_inner_id)
+                .collect(),
+            _ => HashSet::new(),
+        };
+        // budget and group violations also cover active tabs, which are never reclaimed
+        victims.extend(
+            tabid_tabname_tabpid_isActive_map
+                .iter()
+                .filter(eligible)
+                .filter(|tab| {
+                    self.budget_manager.tab_budget_violation.contains_key(&tab.tab_inner_id)
+                        || self.group_manager.tab_group_violation.contains_key(&tab.tab_inner_id)
+                })
+                .map(|tab| tab.tab_inner_id),
+        );
+        victims
+    }
+
+    /// Reclamation state for the status API: pauses and guardrails.
+    pub fn status(&self) -> serde_json::Value {
+        let mut protected_tabs: Vec<i32> = self.protected_tabs.iter().copied().collect();
+        protected_tabs.sort_unstable();
+        serde_json::json!({
+            "paused_by_user": self.paused_by_user,
+            "protected_tabs": protected_tabs,
+            "reclaim_paused": self.reclaim_paused,
+            "consecutive_failures": self.consecutive_failures,
+            "guardrail": self.guardrail_manager.status(self.elapsed_time),
+            "verification": self.verification_manager.status(self.elapsed_time),
+        })
+    }
+
+    /// Drops the state of renderers that exited or were replaced and of tabs that were closed.
+    fn collect_garbage(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) {
+        let processes: HashSet<ProcessKey> = tabid_tabname_tabpid_isActive_map.iter().map(TabProcessInfo::process_key).collect();
+        let tabs: HashSet<i32> = tabid_tabname_tabpid_isActive_map.iter().map(|tab| tab.tab_inner_id).collect();
+        let tab_keys: HashSet<TabKey> = tabid_tabname_tabpid_isActive_map.iter().map(TabKey::of).collect();
+        self.process_memory_series.retain(|process, _| processes.contains(process));
+        self.leak_manager.process_rss_history.retain(|process, _| processes.contains(process));
+        // a frozen renderer whose tab left the snapshot would stay stopped forever, the handle
+        // only reaches it if it is still the same process
+        self.escalation_manager.tab_tier_map.retain(|tab_inner_id, state| {
+            let keep = tabs.contains(tab_inner_id);
+            if !keep && state.tier == Tier::Freeze && signal_process(state.process, Signal::SIGCONT).is_ok() {
+                info!("Resumed process with PID {} of vanished Tab ID: {}", state.process.pid, tab_inner_id);
+            }
+            keep
+        });
+        self.budget_manager.tab_budget_violation.retain(|tab_inner_id, _| tabs.contains(tab_inner_id));
+        self.group_manager.tab_group_violation.retain(|tab_inner_id, _| tabs.contains(tab_inner_id));
+        self.verification_manager.retain_tabs(|tab| tab_keys.contains(tab));
+        self.protected_tabs.retain(|tab_inner_id| tabs.contains(tab_inner_id));
+    }
+
+    /// Leak detection runs with every strategy so suspects always show up in output.json.
+    fn detect_leaks(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) {
+        for tab in self.leak_manager.update(tabid_tabname_tabpid_isActive_map, self.elapsed_time) {
+            if let Some(suspect) = self.leak_manager.leak_suspects.get(&tab.tab_inner_id) {
+                info!(
+                    "Tab ID: {} ({}) is leaking memory: {:.2} KB/s, R²: {:.3}",
+                    tab.tab_inner_id, text_width::truncate(&tab.tab_name, text_width::TITLE_WIDTH), suspect.slope, suspect.r_squared
+                );
+            }
+            self.leak_manager.notify(&tab, &self.channel);
+        }
+    }
+
+    /// Per-tab and per-site budgets are enforced on top of whichever strategy is in use.
+    fn enforce_budgets(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) -> manager_error::Result<()> {
+        let reclaimable: HashSet<i32> = tabid_tabname_tabpid_isActive_map
+            .iter()
+            .filter(|tab| self.is_reclaimable(tab))
+            .map(|tab| tab.tab_inner_id)
+            .collect();
+        let victims = self
+            .budget_manager
+            .check(tabid_tabname_tabpid_isActive_map, |tab| reclaimable.contains(&tab.tab_inner_id));
+        for (tab, reason) in victims.iter() {
+            if self.escalation_manager.tier_of(tab.tab_inner_id).is_released() {
+                continue;
+            }
+            self.escalate_tab(tab, true, "budget", reason);
+        }
+        Ok(())
+    }
+
+    /// Group policies are enforced last: collapsed groups left idle climb the tiers at their usual
+    /// pace, groups over their budget release their biggest tabs right away.
+    fn enforce_groups(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) -> manager_error::Result<()> {
+        let reclaimable: HashSet<i32> = tabid_tabname_tabpid_isActive_map
+            .iter()
+            .filter(|tab| self.is_reclaimable(tab))
+            .map(|tab| tab.tab_inner_id)
+            .collect();
+        let victims = self.group_manager.check(
+            tabid_tabname_tabpid_isActive_map,
+            |tab| reclaimable.contains(&tab.tab_inner_id),
+            history_manager::unix_now(),
+        );
+        for victim in victims.iter() {
+            self.escalate_tab(&victim.tab, victim.under_pressure, "group", &victim.reason);
+        }
+        Ok(())
+    }
+
+    /// Discards through the extension while it is connected, so the tab stays in the tab strip
+    /// and reloads when the user comes back instead of showing a crashed page. Signals otherwise.
+    fn apply_tier(&mut self, tab: &TabProcessInfo, tier: Tier) -> nix::Result<()> {
+        if tier == Tier::Discard && tab.chrome_tab_id != -1 && tab.tab_process_id != -1 && self.channel.is_connected() {
+            // a stopped renderer cannot unload the page
+            signal_process(tab.process_key(), Signal::SIGCONT)?;
+            if self.channel.discard(tab.chrome_tab_id) {
+                // the answer of the extension is checked on the next ticks, see confirm_discards
+                self.verification_manager.expect_discard(tab.chrome_tab_id, tab.process_key(), self.elapsed_time);
+                return Ok(());
+            }
+        }
+        apply_tier(tab.process_key(), tier)
+    }
+
+    /// Terminates the renderers of the discards the extension refused or did not confirm in time,
+    /// unless the user came back to the tab in the meantime.
+    fn confirm_discards(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) {
+        let acks = self.channel.take_discard_acks();
+        for (process, reason) in self.verification_manager.confirm_discards(acks, self.elapsed_time) {
+            if tabid_tabname_tabpid_isActive_map.iter().any(|tab| tab.process_key() == process && tab.is_active) {
+                info!("Extension did not discard Tab ID: {}: {}, the tab is active again", process.tab_inner_id, reason);
+                continue;
+            }
+            warn!("Extension did not discard Tab ID: {}: {}, terminating its renderer", process.tab_inner_id, reason);
+            if let Err(source) = apply_tier(process, Tier::Discard) {
+                self.action_failed(process.pid, Tier::Discard, source);
+            }
+        }
+    }
+
+    /// Resumes every frozen tab, called before the manager exits so no renderer is left stopped.
+    pub fn release_frozen_tabs(&self) {
+        for process in self.escalation_manager.frozen_processes() {
+            let _ = signal_process(process, Signal::SIGCONT);
+        }
+    }
+
+    /// Tabs brought to the foreground go back to the observe tier, and so do active tabs of other
+    /// windows that are past the deepest tier of their window policy. Frozen ones are resumed.
+    fn deescalate_active_tabs(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) -> manager_error::Result<()> {
+        for tab in tabid_tabname_tabpid_isActive_map.iter() {
+            let TabProcessInfo { tab_inner_id, visibility, .. } = tab;
+            let past_policy = match visibility {
+                Visibility::Foreground => true,
+                Visibility::Background => false,
+                _ => self.escalation_manager.tier_of(*tab_inner_id) > self.max_tier(tab).unwrap_or(Tier::Observe),
+            };
+            if !past_policy {
+                continue;
+            }
+            if let Some(state) = self.escalation_manager.deescalate(*tab_inner_id) {
+                info!(
+                    "Tab ID: {} is now {}, leaving tier {}",
+                    tab_inner_id, visibility.as_str(), state.tier
+                );
+                if state.tier == Tier::Freeze && state.process == tab.process_key() {
+                    match signal_process(state.process, Signal::SIGCONT) {
+                        Ok(()) | Err(Errno::ESRCH) => {}
+                        Err(source) => return Err(ManagerError::Signal { pid: state.process.pid, source }),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a candidate tab one step up the escalation ladder and applies the tier action.
+    /// Returns true once the renderer process has been released. A failed action is recorded in
+    /// the audit log and in `tick_error`, the caller goes on with its next victim.
+    fn escalate_tab(&mut self, tab: &TabProcessInfo, under_pressure: bool, strategy: &str, reason: &str) -> bool {
+        // a tab picked by both the strategy and a budget only climbs one tier per tick
+        if self.escalated_tabs.contains(&tab.tab_inner_id) {
+            return false;
+        }
+        if self.protected_tabs.contains(&tab.tab_inner_id) {
+            debug!("Tab ID: {} is protected ({})", tab.tab_inner_id, reason);
+            return false;
+        }
+        if self.group_manager.is_protected(tab) {
+            debug!("Tab ID: {} is in protected group \"{}\" ({})", tab.tab_inner_id, tab.group_title, reason);
+            return false;
+        }
+        let Some(max_tier) = self.max_tier(tab) else {
+            debug!("Tab ID: {} is protected while {} ({})", tab.tab_inner_id, tab.visibility.as_str(), reason);
+            return false;
+        };
+        if self.pattern_manager.is_expected_soon(tab, history_manager::unix_now()) {
+            debug!("Tab ID: {} is usually opened around this time ({})", tab.tab_inner_id, reason);
+            return false;
+        }
+        if self.verification_manager.is_backed_off(TabKey::of(tab), self.elapsed_time) {
+            debug!("Tab ID: {} is backed off after a reload loop ({})", tab.tab_inner_id, reason);
+            return false;
+        }
+        let pending_tier = self.escalation_manager.pending_tier(tab.process_key(), self.elapsed_time, under_pressure);
+        if pending_tier.is_some_and(|tier| tier > max_tier) {
+            debug!("Tab ID: {} stays below tier {} while {} ({})", tab.tab_inner_id, max_tier, tab.visibility.as_str(), reason);
+            return false;
+        }
+        let mut guardrail = self.guardrail_manager.check_reclaim(tab.tab_inner_id, self.elapsed_time);
+        let releasing = pending_tier.is_some_and(|tier| tier.is_released());
+        if guardrail.is_ok() && releasing {
+            guardrail = self.guardrail_manager.check_release(tab.tab_inner_id, self.elapsed_time);
+        }
+        if let Err(refusal) = guardrail {
+            debug!("Guardrail holds back Tab ID: {} ({}): {}", tab.tab_inner_id, reason, refusal);
+            return false;
+        }
+        let Some(tier) = self.escalation_manager.escalate(tab.process_key(), self.elapsed_time, under_pressure) else {
+            return false;
+        };
+        if tier.is_released() {
+            self.guardrail_manager.record_release(self.elapsed_time);
+        }
+        self.escalated_tabs.insert(tab.tab_inner_id);
+        info!(
+            "Escalating process with PID {} (Tab ID: {}) to tier {} due to {}",
+            tab.tab_process_id, tab.tab_inner_id, tier, reason
+        );
+        let result = self.apply_tier(tab, tier);
+        self.tick_actions.push(AuditRecord::new(tab, strategy, reason, tier, &result));
+        if result.is_ok() {
+            self.verification_manager.track(TrackedAction {
+                process: tab.process_key(),
+                tab: TabKey::of(tab),
+                strategy: strategy.to_string(),
+                tier,
+                taken_at: self.elapsed_time,
+                tab_rss_before: tab.tab_rss,
+                browser_rss_before: self.browser_rss,
+            });
+        }
+        if let Err(source) = result {
+            self.action_failed(tab.tab_process_id, tier, source);
+        }
+        tier.is_released()
+    }
+
+    /// Records a failed tier action in `tick_error`, unless it says nothing about the tick.
+    fn action_failed(&mut self, pid: i32, tier: Tier, source: Errno) {
+        match source {
+            // the renderer exited between the snapshot and the action, there is nothing left to reclaim
+            Errno::ESRCH => debug!("Process with PID {} is already gone", pid),
+            // process_madvise needs CAP_SYS_NICE and Linux 5.10, without them the ladder skips the tier
+            Errno::EPERM | Errno::ENOSYS | Errno::EINVAL if tier == Tier::Pageout => {
+                if self.escalation_manager.pageout_supported {
+                    warn!("Pageout is not available ({}), skipping the pageout tier from now on", source);
+                    self.escalation_manager.pageout_supported = false;
                 }
-            } else {
-                self.pid_inActive_time_counter.remove(tab_process_id);
+            }
+            _ => {
+                self.tick_error.get_or_insert(ManagerError::Signal { pid, source });
+            }
+        }
+    }
+
+    /// Idle time is measured from the last time the tab was in use, as reported by the extension,
+    /// so a tab playing media or in front of the user is never idle and a tab left behind is idle
+    /// from the moment the user stopped using it.
+    fn kill_by_inActive_time(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) -> manager_error::Result<()>{
+        let timestamp = history_manager::unix_now();
+        for tab in tabid_tabname_tabpid_isActive_map.iter() {
+            if !self.is_reclaimable(tab) || tab.tab_process_id == -1 {
+                continue;
+            }
+            let idle_time = tab.idle_time(timestamp);
+            if idle_time > self.idel_time_limit as u64 {
+                let reason = format!("inactivity: {} seconds", idle_time);
+                self.escalate_tab(tab, false, "idel_time_limit", &reason);
             }
         }
         Ok(())
     }
 
-    fn kill_by_exceed_rss_limit(&self, tabid_tabname_tabpid_isActive_map: &Vec<(i32, String, i32, i32, bool)>) -> nix::Result<()> {
+    fn kill_by_exceed_rss_limit(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo], reflush_time: u64) -> manager_error::Result<()> {
         let total_rss: i32 = tabid_tabname_tabpid_isActive_map
                 .iter()
-                .map(|&(_, _, _, rss, _)| rss)
+                .map(|tab| tab.tab_rss)
                 .sum();
-        
-            println!(
-                "Chrome Total RSS: {} KB (Limit: {} KB)",
-                total_rss, self.rss_limit
+
+            debug!(
+                "Chrome Total RSS: {} KB (High watermark: {} KB, Low watermark: {} KB)",
+                total_rss, self.rss_high_watermark, self.rss_low_watermark
             );
-        
-            // out of user input rss limit
-            if total_rss > self.rss_limit {
-                // find max rss tab
-                let mut sorted_tabs = tabid_tabname_tabpid_isActive_map.clone();
-                sorted_tabs.sort_by(|a, b| b.3.cmp(&a.3)); // sort by rss
-        
-                //find the biggest rss tab and is_active is false
-                for (tab_inner_id, _, tab_process_id, tab_rss, is_active) in sorted_tabs.iter() {
-                    if !is_active {
-                        // is_active is false that mean the tab is on the background
-                        kill_process(*tab_process_id)?;
-                        println!(
-                            "Killing process with PID {} (Tab ID: {}) due to high memory usage: {} KB",
-                            tab_process_id, tab_inner_id, tab_rss
-                        );
-                        break; 
+
+            // verify what the previous reclaim pass actually achieved, a shortfall corrects the
+            // pageout yield so the plan below asks for more victims
+            if let Some(projection) = self.rss_projection.take() {
+                self.correct_pageout_yield(&projection, tabid_tabname_tabpid_isActive_map);
+                if total_rss > projection.total {
+                    info!(
+                        "Previous reclaim pass projected {} KB, actual {} KB, re-planning with a pageout yield of {:.2}",
+                        projection.total, total_rss, self.pageout_yield
+                    );
+                } else {
+                    debug!("Previous reclaim pass projected {} KB, actual {} KB", projection.total, total_rss);
+                }
+            }
+
+            if !self.rss_reclaiming {
+                self.rss_cooldown_remaining = self.rss_cooldown_remaining.saturating_sub(reflush_time);
+            }
+            if self.rss_reclaiming && total_rss <= self.rss_low_watermark {
+                info!("Reclaim episode finished, cooling down for {} seconds", self.rss_reclaim_cooldown);
+                self.rss_reclaiming = false;
+                self.rss_cooldown_remaining = self.rss_reclaim_cooldown;
+            } else if !self.rss_reclaiming && total_rss > self.rss_high_watermark {
+                // out of user input high watermark
+                if self.rss_cooldown_remaining == 0 {
+                    self.rss_reclaiming = true;
+                } else {
+                    info!("Above high watermark, reclaim cooldown: {} seconds left", self.rss_cooldown_remaining);
+                }
+            }
+
+            if self.rss_reclaiming {
+                let victims = plan_rss_victims(
+                    tabid_tabname_tabpid_isActive_map,
+                    |tab| self.is_reclaimable(tab),
+                    &self.reuse_manager,
+                    total_rss - self.rss_low_watermark,
+                    |tab| self.expected_release(tab),
+                );
+                let expected_rss: i32 = victims.iter().map(|tab| self.expected_release(tab)).sum();
+                if total_rss - expected_rss > self.rss_low_watermark {
+                    warn!(
+                        "Background tabs are expected to give back {} KB this pass, cannot reach low watermark {} KB yet",
+                        expected_rss, self.rss_low_watermark
+                    );
+                }
+
+                let mut projection = RssProjection { total: total_rss, pageouts: Vec::new() };
+                for tab in victims.iter() {
+                    let reason = format!(
+                        "high memory usage: {} KB, reuse score: {:.3}",
+                        tab.tab_rss,
+                        self.reuse_manager.score_of(tab.tab_inner_id).score
+                    );
+                    self.escalate_tab(tab, true, "rss_limit", &reason);
+                    // only the tier the victim actually reached counts
+                    if !self.escalated_tabs.contains(&tab.tab_inner_id) {
+                        continue;
+                    }
+                    let tier = self.escalation_manager.tier_of(tab.tab_inner_id);
+                    projection.total -= (tab.tab_rss as f64 * self.tier_yield(tier)) as i32;
+                    if tier == Tier::Pageout {
+                        projection.pageouts.push((tab.process_key(), tab.tab_rss));
                     }
                 }
+                if projection.total < total_rss {
+                    self.rss_projection = Some(projection);
+                }
             }
             Ok(())
     }
-    
+
+    /// Share of its rss a tab gives back in a tier: a pageout only writes part of it out, a
+    /// frozen renderer keeps all of it, a released one frees everything.
+    fn tier_yield(&self, tier: Tier) -> f64 {
+        match tier {
+            Tier::Observe | Tier::Freeze => 0.0,
+            Tier::Pageout => self.pageout_yield,
+            Tier::Discard | Tier::Kill => 1.0,
+        }
+    }
+
+    /// KB a victim of the rss_limit strategy is expected to give back on the tier it moves to next.
+    fn expected_release(&self, tab: &TabProcessInfo) -> i32 {
+        let tier = self.escalation_manager.pending_tier(tab.process_key(), self.elapsed_time, true);
+        tier.map_or(0, |tier| (tab.tab_rss as f64 * self.tier_yield(tier)) as i32)
+    }
+
+    /// Blends the share of rss the paged out victims of the last pass really gave back into the
+    /// pageout yield. A renderer that exited or was replaced since says nothing about pageout.
+    fn correct_pageout_yield(&mut self, projection: &RssProjection, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) {
+        let observed: Vec<f64> = projection
+            .pageouts
+            .iter()
+            .filter(|(_, rss_before)| *rss_before > 0)
+            .filter_map(|(process, rss_before)| {
+                let tab = tabid_tabname_tabpid_isActive_map.iter().find(|tab| tab.process_key() == *process)?;
+                Some((rss_before - tab.tab_rss).max(0) as f64 / *rss_before as f64)
+            })
+            .collect();
+        if observed.is_empty() {
+            return;
+        }
+        let mean = observed.iter().sum::<f64>() / observed.len() as f64;
+        self.pageout_yield = ((self.pageout_yield + mean) / 2.0).clamp(0.0, 1.0);
+    }
+
+    fn kill_by_leak(&mut self, tabid_tabname_tabpid_isActive_map: &[TabProcessInfo]) -> manager_error::Result<()> {
+        for tab in tabid_tabname_tabpid_isActive_map.iter() {
+            if !self.is_reclaimable(tab) || tab.tab_process_id == -1 {
+                continue;
+            }
+            let Some(suspect) = self.leak_manager.leak_suspects.get(&tab.tab_inner_id) else {
+                continue;
+            };
+            let reason = format!("memory leak: {:.2} KB/s", suspect.slope);
+            self.escalate_tab(tab, false, "leak", &reason);
+        }
+        Ok(())
+    }
+
     fn kill_by_memory_change_rate(
-        &mut self, 
-        tabid_tabname_tabpid_isActive_map: &Vec<(i32, String, i32, i32, bool)>, 
-        rate: f32, 
-        reflush_time: u64
-    ) -> nix::Result<()> {
-        for (_, _, tab_process_id, tab_rss, _) in tabid_tabname_tabpid_isActive_map.iter() {
-            let counter = self.pid_warmup_time_counter.entry(*tab_process_id).or_insert(0);
-            *counter = (*counter + reflush_time as u64).min(u64::MAX);
-            //waiting for 30 to denote average memory usage
-            //every 30 second, check memory rate, and drop the previous memory usage
-            if *counter <= 30 && *tab_process_id != -1 {
-                self.memory_total_using
-                    .entry(*tab_process_id)
-                    .and_modify(|e| *e += *tab_rss as u64)
-                    .or_insert(*tab_rss as u64);
-            } else if let Some(memory_total) = self.memory_total_using.get_mut(tab_process_id) {
-                let memory_average_usage = *memory_total as f32 / (30 / reflush_time) as f32;
-                let memory_change_rate = (*tab_rss as f32 - memory_average_usage).abs() / memory_average_usage;
-                println!(
-                    "Tab ID: {} Memory Change Rate: {} (Average: {})",
-                    tab_process_id, memory_change_rate, memory_average_usage
-                );
-    
-                if *tab_process_id > 0 && memory_change_rate < rate {
-                    kill_process(*tab_process_id)?;
-                    println!(
-                        "Killing process with PID {} due to low memory change rate: {}",
-                        tab_process_id, memory_change_rate
-                    );
-                }
-                *counter = 0;
-                *memory_total = 0;
+        &mut self,
+        tabid_tabname_tabpid_isActive_map: &[TabProcessInfo],
+        rate: f32,
+    ) -> manager_error::Result<()> {
+        for tab in tabid_tabname_tabpid_isActive_map.iter() {
+            let TabProcessInfo { tab_process_id, tab_rss, .. } = tab;
+            if *tab_process_id == -1 {
+                continue;
+            }
+            let series = self
+                .process_memory_series
+                .entry(tab.process_key())
+                .or_insert_with(|| TimeSeries::new(self.memory_change_window, self.ewma_alpha));
+            series.push(self.elapsed_time, *tab_rss);
+            // waiting until the window is full before judging the memory usage
+            if !series.is_full() {
+                continue;
+            }
+            let Some(stats) = series.stats() else {
+                continue;
+            };
+            let Some(memory_change_rate) = stats.change_rate(&self.memory_change_metric, self.memory_change_window) else {
+                continue;
+            };
+            debug!(
+                "Tab ID: {} Memory Change Rate ({}): {:.4} (Mean: {:.0} KB, Stddev: {:.0} KB, Slope: {:.2} KB/s, EWMA: {:.0} KB)",
+                tab_process_id, self.memory_change_metric, memory_change_rate, stats.mean, stats.stddev, stats.slope, stats.ewma
+            );
+
+            if self.is_reclaimable(tab) && memory_change_rate < rate as f64 {
+                let reason = format!("low memory change rate ({}): {:.4}", self.memory_change_metric, memory_change_rate);
+                self.escalate_tab(tab, false, "memory_change_rate", &reason);
             }
         }
         Ok(())
     }
 }
 
-/// Helper wrapper function to kill a process by PID.
-fn kill_process(pid: i32) -> nix::Result<()> {
-    if pid == -1 {
-        println!("Invalid PID: {}, the process resource is already release", pid);
+/// Picks the background tabs to reclaim so that at least `need` KB is expected to be freed in
+/// one pass, `expected_release` is what a victim gives back on the tier it moves to next. A
+/// victim only moving to a tier that frees nothing is still taken, it frees memory next pass.
+///
+/// Tabs are taken by lowest expected reuse per KB freed, so the tabs the user is the least
+/// likely to come back to go first and a big tab only goes first when its score allows it.
+/// On equal scores the biggest tab is taken, which gives the smallest number of victims, and
+/// the last victim is then swapped for the smallest tab of an equal or lower score that still
+/// covers what is left. A tab with a higher score is never swapped in, even when it would throw
+/// away less memory: the set may free more than `need`, but never a tab the user values more.
+fn plan_rss_victims(
+    tabid_tabname_tabpid_isActive_map: &[TabProcessInfo],
+    is_reclaimable: impl Fn(&TabProcessInfo) -> bool,
+    reuse_manager: &ReuseManager,
+    need: i32,
+    expected_release: impl Fn(&TabProcessInfo) -> i32,
+) -> Vec<TabProcessInfo> {
+    let mut candidates: Vec<TabProcessInfo> = tabid_tabname_tabpid_isActive_map
+        .iter()
+        .filter(|tab| is_reclaimable(tab) && tab.tab_process_id != -1)
+        .cloned()
+        .collect();
+    candidates.sort_by(|a, b| {
+        reuse_manager
+            .score_per_kb(a)
+            .total_cmp(&reuse_manager.score_per_kb(b))
+            .then(b.tab_rss.cmp(&a.tab_rss))
+    });
+
+    let mut victims = Vec::new();
+    let mut remaining = need;
+    let mut rest = candidates.into_iter();
+    while remaining > 0 {
+        let Some(tab) = rest.next() else {
+            break;
+        };
+        remaining -= expected_release(&tab);
+        victims.push(tab);
+    }
+
+    if remaining <= 0 {
+        if let Some(last) = victims.pop() {
+            let still_needed = remaining + expected_release(&last);
+            let last_score = reuse_manager.score_per_kb(&last);
+            let smaller = rest
+                .filter(|tab| reuse_manager.score_per_kb(tab) <= last_score && expected_release(tab) >= still_needed)
+                .min_by_key(|tab| expected_release(tab));
+            victims.push(smaller.unwrap_or(last));
+        }
+    }
+    victims
+}
+
+/// Performs the action attached to the tier a tab has just entered.
+fn apply_tier(process: ProcessKey, tier: Tier) -> nix::Result<()> {
+    if tier == Tier::Observe {
         return Ok(());
     }
-    let pid = Pid::from_raw(pid);
-    kill(pid, Signal::SIGKILL).map_err(|err| {
-        eprintln!("Failed to kill process with PID {}: {}", pid, Errno::from_raw(err as i32));
-        err
-    })
+    if process.pid == -1 {
+        warn!("Invalid PID: {}, the process resource is already release", process.pid);
+        return Ok(());
+    }
+    let handle = ProcessHandle::open(process)?;
+    let result = match tier {
+        Tier::Observe => Ok(()),
+        Tier::Pageout => handle.pageout(),
+        Tier::Freeze => handle.signal(Signal::SIGSTOP),
+        Tier::Discard => {
+            // a stopped renderer cannot handle SIGTERM until it is resumed
+            handle.signal(Signal::SIGCONT).and_then(|()| handle.signal(Signal::SIGTERM))
+        }
+        Tier::Kill => handle.signal(Signal::SIGKILL),
+    };
+    result.inspect_err(|err| error!("Failed to apply tier {} to process with PID {}: {}", tier, process.pid, err))
+}
+
+/// Helper wrapper function to send a signal to a renderer that may have exited since it was observed.
+fn signal_process(process: ProcessKey, signal: Signal) -> nix::Result<()> {
+    ProcessHandle::open(process)?
+        .signal(signal)
+        .inspect_err(|err| error!("Failed to send {} to process with PID {}: {}", signal, process.pid, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::ReuseConfig;
+    use crate::reuse_manager::ReuseScore;
+
+    fn tab(tab_inner_id: i32, tab_rss: i32) -> TabProcessInfo {
+        TabProcessInfo { tab_inner_id, tab_process_id: 1000 + tab_inner_id, tab_rss, ..Default::default() }
+    }
+
+    /// Reuse manager scoring each tab `(tab_inner_id, score)`, unlisted tabs score 0.
+    fn reuse_manager(scores: &[(i32, f64)]) -> ReuseManager {
+        let mut reuse_manager = ReuseManager::new(&ReuseConfig::default());
+        for &(tab_inner_id, score) in scores {
+            reuse_manager.tab_scores.insert(tab_inner_id, ReuseScore { score, ..Default::default() });
+        }
+        reuse_manager
+    }
+
+    fn memory_manager() -> MemoryManager {
+        MemoryManager::new(&ConfigManager::minimal(""))
+    }
+
+    /// Runs a tick whose only action fails with `source`.
+    fn failed_tick(memory_manager: &mut MemoryManager, tier: Tier, source: Errno) {
+        memory_manager.tick_error = None;
+        memory_manager.action_failed(1000, tier, source);
+        let result = memory_manager.tick_error.take().map_or(Ok(()), Err);
+        memory_manager.record_tick(&result);
+    }
+
+    #[test]
+    fn unsupported_pageout_does_not_pause_reclamation() {
+        let mut memory_manager = memory_manager();
+        for _ in 0..memory_manager.max_consecutive_failures {
+            failed_tick(&mut memory_manager, Tier::Pageout, Errno::EPERM);
+        }
+        assert_eq!(memory_manager.consecutive_failures, 0);
+        assert!(memory_manager.is_reclaiming());
+        // the ladder goes from observe straight to freeze
+        let process = ProcessKey { tab_inner_id: 1, pid: 1000, start_time: 1 };
+        assert_eq!(memory_manager.escalation_manager.escalate(process, 0, true), Some(Tier::Freeze));
+    }
+
+    #[test]
+    fn failed_signals_pause_reclamation() {
+        let mut memory_manager = memory_manager();
+        for _ in 0..memory_manager.max_consecutive_failures {
+            failed_tick(&mut memory_manager, Tier::Freeze, Errno::EPERM);
+        }
+        assert!(!memory_manager.is_reclaiming());
+        assert!(memory_manager.escalation_manager.pageout_supported);
+    }
+
+    fn plan(tabs: &[TabProcessInfo], reuse_manager: &ReuseManager, need: i32) -> Vec<i32> {
+        plan_rss_victims(tabs, |tab| tab.tab_inner_id != 99, reuse_manager, need, |tab| tab.tab_rss)
+            .iter()
+            .map(|tab| tab.tab_inner_id)
+            .collect()
+    }
+
+    #[test]
+    fn lowest_score_per_kb_goes_first() {
+        let tabs = [tab(1, 100), tab(2, 100), tab(3, 100)];
+        let reuse_manager = reuse_manager(&[(1, 3.0), (2, 1.0), (3, 2.0)]);
+        assert_eq!(plan(&tabs, &reuse_manager, 150), vec![2, 3]);
+    }
+
+    #[test]
+    fn big_tab_goes_first_only_when_its_score_allows() {
+        // 1 is 5x bigger but 10x more likely to be reused: 0.01 per KB against 0.005
+        let tabs = [tab(1, 500), tab(2, 100)];
+        let reuse_manager = reuse_manager(&[(1, 5.0), (2, 0.5)]);
+        assert_eq!(plan(&tabs, &reuse_manager, 50), vec![2]);
+    }
+
+    #[test]
+    fn last_victim_is_swapped_for_a_smaller_tab_of_equal_score() {
+        // unscored tabs tie, the biggest is taken first, then 400 KB are left to cover
+        let tabs = [tab(1, 1000), tab(2, 800), tab(3, 450), tab(4, 300)];
+        let reuse_manager = reuse_manager(&[]);
+        assert_eq!(plan(&tabs, &reuse_manager, 1400), vec![1, 3]);
+    }
+
+    #[test]
+    fn higher_score_is_never_swapped_in() {
+        let tabs = [tab(1, 1000), tab(2, 800), tab(3, 450)];
+        let reuse_manager = reuse_manager(&[(3, 100.0)]);
+        assert_eq!(plan(&tabs, &reuse_manager, 1400), vec![1, 2]);
+    }
+
+    #[test]
+    fn unreclaimable_and_gone_tabs_are_skipped() {
+        let mut gone = tab(2, 1000);
+        gone.tab_process_id = -1;
+        let tabs = [tab(99, 5000), gone, tab(3, 100)];
+        assert_eq!(plan(&tabs, &reuse_manager(&[]), 2000), vec![3]);
+        assert!(plan(&tabs, &reuse_manager(&[]), 0).is_empty());
+    }
+
+    #[test]
+    fn victims_freeing_nothing_this_pass_are_still_taken() {
+        let tabs = [tab(1, 1000), tab(2, 500)];
+        let victims: Vec<i32> = plan_rss_victims(&tabs, |_| true, &reuse_manager(&[]), 400, |tab| if tab.tab_inner_id == 1 { 0 } else { tab.tab_rss })
+            .iter()
+            .map(|tab| tab.tab_inner_id)
+            .collect();
+        assert_eq!(victims, vec![1, 2]);
+    }
 }
@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use log::{info, warn};
+
+use crate::budget_manager::url_host;
+use crate::config_manager::{PatternConfig, ReuseConfig};
+use crate::history_manager::HistoryRecord;
+use crate::reuse_manager::ReuseManager;
+use crate::tab_manager::TabProcessInfo;
+
+const HOUR: u64 = 3600;
+const WEEK: u64 = 7 * 24 * HOUR;
+const HOURS_PER_WEEK: usize = 7 * 24;
+
+/// Activations of one site, decayed counts per hour of the week (0 is Sunday midnight, local time).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SitePattern {
+    pub buckets: Vec<f64>,
+    pub first_seen: u64, // unix second
+    pub updated_at: u64, // unix second the buckets were last decayed
+}
+
+impl SitePattern {
+    fn new(timestamp: u64) -> Self {
+        Self { buckets: vec![0.0; HOURS_PER_WEEK], first_seen: timestamp, updated_at: timestamp }
+    }
+}
+
+/// Learns when each site is opened, by hour of the day and day of the week, from the stream of
+/// `active` flags, e.g. mail every working day morning.
+///
+/// Old activations fade with `half_life` so a changed habit is picked up. The likelihood of a site
+/// is the chance it is activated within the next `lookahead` hours, estimated from the same hours
+/// of the same weekday and of every day. The model is kept in `path` across restarts.
+pub struct PatternManager {
+    pub config: PatternConfig,
+    pub path: Option<PathBuf>, // None keeps the model in memory, for the offline evaluation
+    pub sites: HashMap<String, SitePattern>, // url host -> pattern
+    pub tab_was_active: HashMap<i32, bool>,  // tab_inner_id -> active on the previous tick
+    pub saved_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PatternFile {
+    sites: HashMap<String, SitePattern>,
+}
+
+impl PatternManager {
+    pub fn new(config: &PatternConfig) -> Self {
+        let path = PathBuf::from(&config.path);
+        let sites = match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<PatternFile>(&content) {
+                Ok(file) => {
+                    info!("Loaded activation patterns of {} sites from {}", file.sites.len(), path.display());
+                    file.sites
+                }
+                Err(err) => {
+                    warn!("Ignoring activation patterns in {}: {}", path.display(), err);
+                    HashMap::new()
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                warn!("Failed to read activation patterns from {}: {}", path.display(), err);
+                HashMap::new()
+            }
+        };
+        Self { config: config.clone(), path: Some(path), sites, tab_was_active: HashMap::new(), saved_at: 0 }
+    }
+
+    pub fn in_memory(config: &PatternConfig) -> Self {
+        Self { config: config.clone(), path: None, sites: HashMap::new(), tab_was_active: HashMap::new(), saved_at: 0 }
+    }
+
+    /// Records the tabs that came to the foreground since the previous tick.
+    pub fn observe(&mut self, tabs: &[TabProcessInfo], timestamp: u64) {
+        self.tab_was_active.retain(|tab_inner_id, _| tabs.iter().any(|tab| tab.tab_inner_id == *tab_inner_id));
+        for tab in tabs.iter() {
+            let was_active = self.tab_was_active.insert(tab.tab_inner_id, tab.is_active).unwrap_or(false);
+            let host = url_host(&tab.url);
+            if !tab.is_active || was_active || host.is_empty() {
+                continue;
+            }
+            let half_life = self.config.half_life;
+            let site = self.sites.entry(host.to_string()).or_insert_with(|| SitePattern::new(timestamp));
+            decay(site, timestamp, half_life);
+            site.buckets[hour_of_week(timestamp)] += 1.0;
+        }
+    }
+
+    /// Chance that the site of `url` is activated within the next `lookahead` hours.
+    pub fn likelihood(&self, url: &str, timestamp: u64) -> f64 {
+        let Some(site) = self.sites.get(url_host(url)) else {
+            return 0.0;
+        };
+        let fade = fade(site.updated_at, timestamp, self.config.half_life);
+        let weeks = self.effective_weeks(site, timestamp);
+        let hour = hour_of_week(timestamp);
+        let mut expected = 0.0;
+        for offset in 0..self.config.lookahead.max(1) as usize {
+            let bucket = (hour + offset) % HOURS_PER_WEEK;
+            let same_weekday = site.buckets[bucket];
+            // the same hour on every day of the week, for habits that do not depend on the day
+            let every_day = (0..7).map(|day| site.buckets[(bucket % 24) + day * 24]).sum::<f64>() / 7.0;
+            expected += (same_weekday + every_day) / 2.0 * fade / weeks;
+        }
+        // activations within the lookahead as a Poisson process
+        1.0 - (-expected).exp()
+    }
+
+    /// True when the tab is expected soon enough to be kept whatever the strategy says. A site seen
+    /// for less than `min_history` is never protected, a few activations say nothing of a habit yet.
+    pub fn is_expected_soon(&self, tab: &TabProcessInfo, timestamp: u64) -> bool {
+        if self.config.protect_likelihood <= 0.0 {
+            return false;
+        }
+        let Some(site) = self.sites.get(url_host(&tab.url)) else {
+            return false;
+        };
+        timestamp.saturating_sub(site.first_seen) >= self.config.min_history
+            && self.likelihood(&tab.url, timestamp) >= self.config.protect_likelihood
+    }
+
+    /// Writes the model once `save_interval` has passed since the last save.
+    pub fn save_if_due(&mut self, timestamp: u64) -> io::Result<()> {
+        if timestamp < self.saved_at + self.config.save_interval {
+            return Ok(());
+        }
+        self.save(timestamp)
+    }
+
+    pub fn save(&mut self, timestamp: u64) -> io::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = PatternFile { sites: self.sites.clone() };
+        // written aside then renamed, a crash never leaves a truncated model behind
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_string(&file)?)?;
+        fs::rename(&temp_path, path)?;
+        self.saved_at = timestamp;
+        Ok(())
+    }
+
+    /// Weeks of history behind the buckets, counting faded weeks for less.
+    fn effective_weeks(&self, site: &SitePattern, timestamp: u64) -> f64 {
+        let span = timestamp.saturating_sub(site.first_seen) as f64;
+        let weeks = if self.config.half_life == 0 {
+            span / WEEK as f64
+        } else {
+            let half_life = self.config.half_life as f64;
+            half_life / std::f64::consts::LN_2 * (1.0 - 0.5f64.powf(span / half_life)) / WEEK as f64
+        };
+        weeks.max(1.0)
+    }
+}
+
+fn fade(from: u64, to: u64, half_life: u64) -> f64 {
+    if half_life == 0 {
+        return 1.0;
+    }
+    0.5f64.powf(to.saturating_sub(from) as f64 / half_life as f64)
+}
+
+fn decay(site: &mut SitePattern, timestamp: u64, half_life: u64) {
+    let fade = fade(site.updated_at, timestamp, half_life);
+    for bucket in site.buckets.iter_mut() {
+        *bucket *= fade;
+    }
+    site.updated_at = site.updated_at.max(timestamp);
+}
+
+/// Hour of the week in local time, 0 is Sunday between midnight and 1 am.
+fn hour_of_week(timestamp: u64) -> usize {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call, localtime_r is thread safe
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return ((timestamp / HOUR) % HOURS_PER_WEEK as u64) as usize;
+    }
+    tm.tm_wday as usize * 24 + tm.tm_hour as usize
+}
+
+/// Outcome of replaying a recorded trace with one victim selection policy.
+pub struct Evaluation {
+    pub policy: &'static str,
+    pub reclaims: u64,
+    pub reloads: u64, // activations of a tab the policy had reclaimed
+}
+
+/// Replays the history store with the rss_limit strategy and counts the reloads each policy causes.
+///
+/// Every policy sees the same ticks and learns online, nothing after a tick is known when it is
+/// decided. A reclaimed tab holds no memory until it is activated again, which counts as a reload.
+/// `lru` only looks at recency, `reuse` is the `[reuse]` score and `patterns` adds the activation
+/// patterns to it.
+pub fn evaluate(
+    records: &[HistoryRecord],
+    reuse_config: &ReuseConfig,
+    pattern_config: &PatternConfig,
+    rss_high_watermark: i32,
+    rss_low_watermark: i32,
+) -> (u64, Vec<Evaluation>) {
+    let lru_config = ReuseConfig {
+        recency_weight: 1.0,
+        frequency_weight: 0.0,
+        age_weight: 0.0,
+        reload_cost_weight: 0.0,
+        priority_weight: 0.0,
+        pattern_weight: 0.0,
+        ..reuse_config.clone()
+    };
+    let without_patterns = ReuseConfig { pattern_weight: 0.0, ..reuse_config.clone() };
+    let mut policies = [
+        Replay::new("lru", &lru_config, pattern_config, false),
+        Replay::new("reuse", &without_patterns, pattern_config, false),
+        Replay::new("patterns", reuse_config, pattern_config, true),
+    ];
+
+    let mut activations = 0;
+    let mut was_active: HashMap<i32, bool> = HashMap::new();
+    let start = records.first().map_or(0, |record| record.timestamp);
+    for tick in records.chunk_by(|a, b| a.timestamp == b.timestamp) {
+        let timestamp = tick[0].timestamp;
+        // tabs without a renderer in the trace were reclaimed for real, they are left out
+        let tabs: Vec<TabProcessInfo> = tick
+            .iter()
+            .filter(|record| record.pid != -1)
+            .map(|record| TabProcessInfo {
+                tab_inner_id: record.tab_id,
+                tab_name: record.tab_name.clone(),
+                tab_process_id: record.pid,
+                tab_rss: record.rss,
+                is_active: record.is_active,
+                url: record.url.clone(),
+                ..TabProcessInfo::default()
+            })
+            .collect();
+        for tab in tabs.iter() {
+            if tab.is_active && !was_active.get(&tab.tab_inner_id).copied().unwrap_or(false) {
+                activations += 1;
+            }
+        }
+        was_active = tabs.iter().map(|tab| (tab.tab_inner_id, tab.is_active)).collect();
+        for policy in policies.iter_mut() {
+            policy.tick(&tabs, timestamp, timestamp - start, rss_high_watermark, rss_low_watermark);
+        }
+    }
+    (activations, policies.into_iter().map(|policy| policy.evaluation).collect())
+}
+
+struct Replay {
+    reuse_manager: ReuseManager,
+    pattern_manager: PatternManager,
+    protect_expected: bool,
+    reclaimed: HashSet<i32>,
+    reclaiming: bool,
+    evaluation: Evaluation,
+}
+
+impl Replay {
+    fn new(policy: &'static str, reuse_config: &ReuseConfig, pattern_config: &PatternConfig, protect_expected: bool) -> Self {
+        Self {
+            reuse_manager: ReuseManager::new(reuse_config),
+            pattern_manager: PatternManager::in_memory(pattern_config),
+            protect_expected,
+            reclaimed: HashSet::new(),
+            reclaiming: false,
+            evaluation: Evaluation { policy, reclaims: 0, reloads: 0 },
+        }
+    }
+
+    fn tick(&mut self, tabs: &[TabProcessInfo], timestamp: u64, elapsed_time: u64, high_watermark: i32, low_watermark: i32) {
+        self.reclaimed.retain(|tab_inner_id| tabs.iter().any(|tab| tab.tab_inner_id == *tab_inner_id));
+        for tab in tabs.iter().filter(|tab| tab.is_active) {
+            if self.reclaimed.remove(&tab.tab_inner_id) {
+                self.evaluation.reloads += 1;
+            }
+        }
+        self.pattern_manager.observe(tabs, timestamp);
+        self.reuse_manager.update(tabs, elapsed_time, &self.pattern_manager, timestamp);
+
+        let total_rss: i32 = tabs
+            .iter()
+            .filter(|tab| !self.reclaimed.contains(&tab.tab_inner_id))
+            .map(|tab| tab.tab_rss)
+            .sum();
+        if self.reclaiming && total_rss <= low_watermark {
+            self.reclaiming = false;
+        } else if total_rss > high_watermark {
+            self.reclaiming = true;
+        }
+        if !self.reclaiming {
+            return;
+        }
+
+        let mut candidates: Vec<&TabProcessInfo> = tabs
+            .iter()
+            .filter(|tab| !tab.is_active && !self.reclaimed.contains(&tab.tab_inner_id))
+            .filter(|tab| !self.protect_expected || !self.pattern_manager.is_expected_soon(tab, timestamp))
+            .collect();
+        candidates.sort_by(|a, b| {
+            self.reuse_manager
+                .score_per_kb(a)
+                .total_cmp(&self.reuse_manager.score_per_kb(b))
+                .then(b.tab_rss.cmp(&a.tab_rss))
+        });
+        let mut remaining = total_rss - low_watermark;
+        for tab in candidates {
+            if remaining <= 0 {
+                break;
+            }
+            remaining -= tab.tab_rss;
+            self.reclaimed.insert(tab.tab_inner_id);
+            self.evaluation.reclaims += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * HOUR;
+    const NOW: u64 = 1_700_000_000;
+
+    fn config() -> PatternConfig {
+        PatternConfig { half_life: 0, ..PatternConfig::default() }
+    }
+
+    fn manager_with(activations: f64, first_seen: u64) -> PatternManager {
+        let mut manager = PatternManager::in_memory(&config());
+        let mut site = SitePattern::new(first_seen);
+        site.buckets[hour_of_week(NOW)] = activations;
+        site.updated_at = NOW;
+        manager.sites.insert("mail.example.com".to_string(), site);
+        manager
+    }
+
+    fn tab(tab_inner_id: i32, rss: i32, is_active: bool) -> TabProcessInfo {
+        TabProcessInfo {
+            tab_inner_id,
+            tab_process_id: 1000 + tab_inner_id,
+            tab_rss: rss,
+            is_active,
+            url: format!("https://site{}.example.com/", tab_inner_id),
+            ..TabProcessInfo::default()
+        }
+    }
+
+    fn record(timestamp: u64, tab: &TabProcessInfo) -> HistoryRecord {
+        HistoryRecord {
+            timestamp,
+            tab_id: tab.tab_inner_id,
+            tab_name: String::new(),
+            url: tab.url.clone(),
+            pid: tab.tab_process_id,
+            rss: tab.tab_rss,
+            is_active: tab.is_active,
+            action: None,
+        }
+    }
+
+    #[test]
+    fn likelihood_of_an_unknown_site_is_zero() {
+        let manager = manager_with(3.0, NOW);
+        assert_eq!(manager.likelihood("https://other.example.com/", NOW), 0.0);
+    }
+
+    #[test]
+    fn likelihood_spreads_activations_over_the_weeks_of_history() {
+        // 3 activations at this hour, half counted for the same weekday and half for every day
+        let expected: f64 = (3.0 + 3.0 / 7.0) / 2.0;
+        let new_site = manager_with(3.0, NOW);
+        assert!((new_site.likelihood("https://mail.example.com/inbox", NOW) - (1.0 - (-expected).exp())).abs() < 1e-9);
+        let old_site = manager_with(3.0, NOW - 2 * WEEK);
+        assert!((old_site.likelihood("https://mail.example.com/inbox", NOW) - (1.0 - (-expected / 2.0).exp())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_new_site_is_not_protected_before_min_history() {
+        let mail = TabProcessInfo { url: "https://mail.example.com/".to_string(), ..TabProcessInfo::default() };
+        let new_site = manager_with(3.0, NOW - DAY);
+        assert!(new_site.likelihood(&mail.url, NOW) >= new_site.config.protect_likelihood);
+        assert!(!new_site.is_expected_soon(&mail, NOW));
+        let old_site = manager_with(6.0, NOW - 8 * DAY);
+        assert!(old_site.is_expected_soon(&mail, NOW));
+    }
+
+    #[test]
+    fn evaluate_counts_reclaims_and_reloads_of_each_policy() {
+        let records = vec![
+            record(NOW, &tab(1, 100, true)),
+            record(NOW, &tab(2, 100, false)),
+            record(NOW + 60, &tab(1, 100, false)),
+            record(NOW + 60, &tab(2, 100, true)),
+        ];
+        let (activations, evaluations) = evaluate(&records, &ReuseConfig::default(), &config(), 150, 100);
+        assert_eq!(activations, 2);
+        assert_eq!(evaluations.iter().map(|evaluation| evaluation.policy).collect::<Vec<_>>(), ["lru", "reuse", "patterns"]);
+        for evaluation in evaluations {
+            // tab 2 is reclaimed then reopened, tab 1 is reclaimed once it goes to the background
+            assert_eq!((evaluation.reclaims, evaluation.reloads), (2, 1), "{}", evaluation.policy);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use log::warn;
+
+/// Identity of a renderer: the tab it serves, its pid and its start time in clock ticks since boot.
+/// A pid alone can be reused by an unrelated process once the renderer exits, the triple cannot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessKey {
+    pub tab_inner_id: i32,
+    pub pid: i32,
+    pub start_time: u64,
+}
+
+/// Start time of a process, field 22 of /proc/<pid>/stat.
+pub fn process_start_time(pid: i32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name in parentheses may contain spaces, fields are counted after it
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Value of `--renderer-client-id=` in a renderer command line.
+pub fn renderer_client_id(cmdline: &str) -> Option<i32> {
+    let key = "--renderer-client-id=";
+    let start = cmdline.find(key)? + key.len();
+    let end = cmdline[start..].find([' ', '\0']).map_or(cmdline.len(), |end| start + end);
+    cmdline[start..end].parse().ok()
+}
+
+/// A pidfd on a renderer, only opened once the process behind the pid is checked to still be the
+/// renderer of the tab. Signals go through the pidfd, so they cannot reach a process that took
+/// over the pid afterwards.
+pub struct ProcessHandle {
+    pub key: ProcessKey,
+    pidfd: OwnedFd,
+}
+
+impl ProcessHandle {
+    /// Fails with `ESRCH` when the renderer is gone, including when its pid now belongs to another process.
+    pub fn open(key: ProcessKey) -> nix::Result<Self> {
+        if key.pid == -1 {
+            return Err(Errno::ESRCH);
+        }
+        // SAFETY: plain syscall, the returned descriptor is owned by the handle
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, key.pid, 0) };
+        if pidfd < 0 {
+            return Err(Errno::last());
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as libc::c_int) };
+
+        // checked after pidfd_open: if the pid was already reused the pidfd points at the new
+        // process and the check fails, if it is reused later the pidfd still points at ours
+        let start_time = process_start_time(key.pid);
+        let client_id = fs::read_to_string(format!("/proc/{}/cmdline", key.pid))
+            .ok()
+            .and_then(|cmdline| renderer_client_id(&cmdline));
+        if start_time != Some(key.start_time) || client_id != Some(key.tab_inner_id) {
+            warn!(
+                "PID {} no longer belongs to the renderer of Tab ID: {}, leaving it alone",
+                key.pid, key.tab_inner_id
+            );
+            return Err(Errno::ESRCH);
+        }
+        Ok(Self { key, pidfd })
+    }
+
+    pub fn signal(&self, signal: Signal) -> nix::Result<()> {
+        // SAFETY: plain syscall on a descriptor owned by the handle, no siginfo is passed
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.pidfd.as_raw_fd(),
+                signal as libc::c_int,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(Errno::last());
+        }
+        Ok(())
+    }
+
+    /// Asks the kernel to reclaim the anonymous memory of the process with `process_madvise(MADV_PAGEOUT)`.
+    /// The pages are swapped out (or compressed into zram) and fault back in when the tab is used again.
+    pub fn pageout(&self) -> nix::Result<()> {
+        let maps = fs::read_to_string(format!("/proc/{}/maps", self.key.pid)).map_err(|_| Errno::ESRCH)?;
+        let mut iovecs: Vec<libc::iovec> = Vec::new();
+        for line in maps.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(range), Some(perms)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            // only private writable mappings hold anonymous pages worth paging out
+            if !perms.starts_with("rw") || !perms.ends_with('p') {
+                continue;
+            }
+            let Some((start, end)) = range.split_once('-') else {
+                continue;
+            };
+            if let (Ok(start), Ok(end)) = (usize::from_str_radix(start, 16), usize::from_str_radix(end, 16)) {
+                iovecs.push(libc::iovec { iov_base: start as *mut libc::c_void, iov_len: end - start });
+            }
+        }
+
+        // the kernel accepts at most UIO_MAXIOV (1024) ranges per call
+        for chunk in iovecs.chunks(1024) {
+            // SAFETY: plain syscall, the iovecs only describe ranges of the target process and are never dereferenced here
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_process_madvise,
+                    self.pidfd.as_raw_fd(),
+                    chunk.as_ptr(),
+                    chunk.len(),
+                    libc::MADV_PAGEOUT,
+                    0,
+                )
+            };
+            if ret < 0 {
+                return Err(Errno::last());
+            }
+        }
+        Ok(())
+    }
+}
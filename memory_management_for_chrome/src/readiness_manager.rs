@@ -0,0 +1,289 @@
+use log::{info, warn};
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use crate::channel_manager::ChannelManager;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+type Check = Box<dyn Fn() -> Result<(), String>>;
+
+/// An input the manager depends on.
+pub struct Probe {
+    pub name: &'static str,
+    pub ready: bool,
+    pub detail: String, // why the probe is not ready
+    check: Check,       // full probe, only run while the input is not ready
+    keep: Option<Check>, // cheap check run while it is ready, None keeps it ready until another input goes away
+}
+
+impl Probe {
+    fn run(&mut self) -> bool {
+        match (&self.keep, self.ready) {
+            (_, false) => self.probe(),
+            (Some(keep), true) => {
+                let result = keep();
+                self.update(result)
+            }
+            (None, true) => true,
+        }
+    }
+
+    fn probe(&mut self) -> bool {
+        let result = (self.check)();
+        self.update(result)
+    }
+
+    fn update(&mut self, result: Result<(), String>) -> bool {
+        let ready = result.is_ok();
+        match (self.ready, result) {
+            (false, Ok(())) => info!("{} is available", self.name),
+            (true, Err(detail)) => {
+                warn!("{} is no longer available: {}", self.name, detail);
+                self.detail = detail;
+            }
+            (false, Err(detail)) => self.detail = detail,
+            (true, Ok(())) => {}
+        }
+        self.ready = ready;
+        ready
+    }
+}
+
+/// Tracks the inputs of the main loop: the tab info server, the tab snapshot it writes and
+/// the process source of the kernel module, or the extension channel and its snapshot.
+///
+/// Tabs are only observed (output.json, history) while the snapshot and the process source
+/// are readable, and only reclaimed once every input is available, so a stale snapshot
+/// never leads to killing the wrong tab.
+pub struct ReadinessManager {
+    pub ingestion: Probe,
+    pub snapshot: Probe,
+    pub process_source: Probe,
+}
+
+impl ReadinessManager {
+    /// `snapshot_max_age` is how old, in second, log.json may be before the snapshot is stale.
+    pub fn new(ingestion_address: &str, log_path: PathBuf, chrome_info_path: PathBuf, snapshot_max_age: u64) -> Self {
+        let address = ingestion_address.to_string();
+        let ingestion = Probe {
+            name: "tab info server",
+            ready: false,
+            detail: format!("nothing listens on {}", address),
+            check: Box::new(move || {
+                let socket_address = address
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addresses| addresses.next())
+                    .ok_or_else(|| format!("invalid address {}", address))?;
+                TcpStream::connect_timeout(&socket_address, Duration::from_millis(200))
+                    .map(|_| ())
+                    .map_err(|err| format!("{}: {}", address, err))
+            }),
+            keep: None, // a server that went away leaves the snapshot stale
+        };
+        let keep_path = log_path.clone();
+        let snapshot = Probe {
+            name: "tab snapshot",
+            ready: false,
+            detail: "no tab list received from the browser extension yet".to_string(),
+            check: Box::new(move || {
+                check_fresh(&log_path, snapshot_max_age)?;
+                let content = fs::read_to_string(&log_path).map_err(|err| format!("{}: {}", log_path.display(), err))?;
+                json::parse(&content)
+                    .map(|_| ())
+                    .map_err(|err| format!("{}: {}", log_path.display(), err))
+            }),
+            keep: Some(Box::new(move || check_fresh(&keep_path, snapshot_max_age))),
+        };
+        Self { ingestion, snapshot, process_source: process_source_probe(chrome_info_path) }
+    }
+
+    /// Inputs when the tabs come over the extension channel: an extension has to be connected
+    /// and to have sent its snapshot on that connection.
+    pub fn with_channel(channel_manager: &ChannelManager, chrome_info_path: PathBuf) -> Self {
+        // both checks only read a flag, they run on every tick
+        let connected = || {
+            let sender = channel_manager.sender.clone();
+            Box::new(move || if sender.is_connected() { Ok(()) } else { Err("no extension connected".to_string()) }) as Check
+        };
+        let synced = || {
+            let synced = channel_manager.synced.clone();
+            Box::new(move || {
+                if synced.load(Ordering::SeqCst) {
+                    Ok(())
+                } else {
+                    Err("no tab list received from the browser extension yet".to_string())
+                }
+            }) as Check
+        };
+        let ingestion = Probe {
+            name: "extension channel",
+            ready: false,
+            detail: "no extension connected".to_string(),
+            check: connected(),
+            keep: Some(connected()),
+        };
+        let snapshot = Probe {
+            name: "tab snapshot",
+            ready: false,
+            detail: "no tab list received from the browser extension yet".to_string(),
+            check: synced(),
+            keep: Some(synced()),
+        };
+        Self { ingestion, snapshot, process_source: process_source_probe(chrome_info_path) }
+    }
+
+    /// Probes the inputs that are not available and checks the others are still there, returns
+    /// true when all of them are available. Once one goes away every input is probed again.
+    pub fn refresh(&mut self) -> bool {
+        let was_ready = self.all_ready();
+        self.ingestion.run();
+        self.snapshot.run();
+        self.process_source.run();
+        if was_ready && !self.all_ready() {
+            for probe in [&mut self.ingestion, &mut self.snapshot, &mut self.process_source] {
+                if probe.ready {
+                    probe.probe();
+                }
+            }
+        }
+        self.all_ready()
+    }
+
+    /// Polls the inputs until all of them are available or `timeout` expires.
+    pub fn wait(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.refresh() {
+            if Instant::now() >= deadline {
+                for probe in self.probes().filter(|probe| !probe.ready) {
+                    warn!("Startup: {} is not available: {}", probe.name, probe.detail);
+                }
+                return false;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        true
+    }
+
+    pub fn all_ready(&self) -> bool {
+        self.probes().all(|probe| probe.ready)
+    }
+
+    /// Tabs can be listed with their processes even if they must not be reclaimed.
+    pub fn can_observe(&self) -> bool {
+        self.snapshot.ready && self.process_source.ready
+    }
+
+    /// One line summary for logs and the systemd status.
+    pub fn status(&self) -> String {
+        if self.all_ready() {
+            return "all inputs available".to_string();
+        }
+        let missing: Vec<String> = self
+            .probes()
+            .filter(|probe| !probe.ready)
+            .map(|probe| format!("{} ({})", probe.name, probe.detail))
+            .collect();
+        let mode = if self.can_observe() { "observe only" } else { "waiting" };
+        format!("degraded, {}: missing {}", mode, missing.join(", "))
+    }
+
+    fn probes(&self) -> impl Iterator<Item = &Probe> {
+        [&self.ingestion, &self.snapshot, &self.process_source].into_iter()
+    }
+}
+
+fn process_source_probe(chrome_info_path: PathBuf) -> Probe {
+    let keep_path = chrome_info_path.clone();
+    Probe {
+        name: "process source",
+        ready: false,
+        detail: "kernel module is not loaded".to_string(),
+        check: Box::new(move || {
+            fs::File::open(&chrome_info_path)
+                .map(|_| ())
+                .map_err(|err| format!("{}: {} (load the kernel module)", chrome_info_path.display(), err))
+        }),
+        keep: Some(Box::new(move || {
+            if keep_path.exists() {
+                Ok(())
+            } else {
+                Err(format!("{} is gone (kernel module unloaded)", keep_path.display()))
+            }
+        })),
+    }
+}
+
+/// Fails when `path` was last written more than `max_age` seconds ago.
+fn check_fresh(path: &Path, max_age: u64) -> Result<(), String> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default().as_secs();
+    if age > max_age {
+        return Err(format!("{} not updated for {} seconds", path.display(), age));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Fresh directory with a process source, plus a listening ingestion address.
+    fn inputs(name: &str) -> (PathBuf, TcpListener) {
+        let dir = std::env::temp_dir().join(format!("readiness-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("chrome_info"), "").unwrap();
+        (dir, TcpListener::bind("127.0.0.1:0").unwrap())
+    }
+
+    fn readiness_manager(dir: &Path, listener: &TcpListener) -> ReadinessManager {
+        let address = listener.local_addr().unwrap().to_string();
+        ReadinessManager::new(&address, dir.join("log.json"), dir.join("chrome_info"), 30)
+    }
+
+    #[test]
+    fn missing_snapshot_is_degraded() {
+        let (dir, listener) = inputs("missing");
+        let mut readiness_manager = readiness_manager(&dir, &listener);
+        assert!(!readiness_manager.refresh());
+        assert!(readiness_manager.ingestion.ready && readiness_manager.process_source.ready);
+        assert!(!readiness_manager.can_observe());
+        assert!(readiness_manager.status().starts_with("degraded, waiting: missing tab snapshot"));
+
+        fs::write(dir.join("log.json"), "[]").unwrap();
+        assert!(readiness_manager.refresh());
+        assert_eq!(readiness_manager.status(), "all inputs available");
+    }
+
+    #[test]
+    fn stale_snapshot_is_degraded() {
+        let (dir, listener) = inputs("stale");
+        fs::write(dir.join("log.json"), "[]").unwrap();
+        let mut readiness_manager = readiness_manager(&dir, &listener);
+        assert!(readiness_manager.refresh());
+
+        // the extension stopped posting a minute ago
+        let file = fs::File::options().write(true).open(dir.join("log.json")).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(60)).unwrap();
+        assert!(!readiness_manager.refresh());
+        assert!(!readiness_manager.snapshot.ready);
+        assert!(readiness_manager.snapshot.detail.contains("not updated for"));
+        assert!(readiness_manager.status().contains("missing tab snapshot"));
+    }
+
+    #[test]
+    fn unreadable_snapshot_is_degraded() {
+        let (dir, listener) = inputs("invalid");
+        fs::write(dir.join("log.json"), "[{\"id\":").unwrap();
+        let mut readiness_manager = readiness_manager(&dir, &listener);
+        assert!(!readiness_manager.refresh());
+        assert!(!readiness_manager.snapshot.ready);
+    }
+}
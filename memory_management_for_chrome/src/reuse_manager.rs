@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use crate::budget_manager::{matches_pattern, url_host};
+use crate::config_manager::ReuseConfig;
+use crate::pattern_manager::PatternManager;
+use crate::tab_manager::TabProcessInfo;
+
+const HOUR: f64 = 3600.0;
+
+/// Activations of a tab seen in the stream of `active` flags, times are elapsed time.
+pub struct TabActivity {
+    pub first_seen: u64,
+    pub last_active: u64, // first seen if never active
+    pub activations: u32, // background to foreground transitions, the first sighting counts when active
+    pub was_active: bool,
+}
+
+/// Parts of the expected reuse of a tab, each between 0 and 1 except `priority`.
+#[derive(Clone, Copy, Default)]
+pub struct ReuseScore {
+    pub score: f64, // weighted sum of the parts below
+    pub recency: f64,
+    pub frequency: f64,
+    pub age: f64,
+    pub reload_cost: f64,
+    pub priority: f64,
+    pub pattern: f64, // chance the site is opened within the pattern lookahead
+}
+
+impl ReuseScore {
+    pub fn to_json(self) -> serde_json::Value {
+        serde_json::json!({
+            "score": self.score,
+            "recency": self.recency,
+            "frequency": self.frequency,
+            "age": self.age,
+            "reload_cost": self.reload_cost,
+            "priority": self.priority,
+            "pattern": self.pattern,
+        })
+    }
+}
+
+/// Estimates how likely the user is to come back to each tab, from the `[reuse]` section of manager.toml.
+///
+/// A recently or often activated tab, a young tab, a tab that is costly to reload (big or pinned),
+/// a tab of a prioritized site and a tab whose site is usually opened around this time score high.
+/// The RSS strategy reclaims the tabs with the lowest score per KB freed first.
+pub struct ReuseManager {
+    pub config: ReuseConfig,
+    pub tab_activity: HashMap<i32, TabActivity>, // tab_inner_id -> activity
+    pub tab_scores: HashMap<i32, ReuseScore>,   // tab_inner_id -> score, refreshed every tick
+}
+
+impl ReuseManager {
+    pub fn new(config: &ReuseConfig) -> Self {
+        Self {
+            config: config.clone(),
+            tab_activity: HashMap::new(),
+            tab_scores: HashMap::new(),
+        }
+    }
+
+    /// Records the activations of this tick and scores every tab again, `timestamp` is the unix time
+    /// the activation patterns are looked up at.
+    pub fn update(&mut self, tabs: &[TabProcessInfo], elapsed_time: u64, pattern_manager: &PatternManager, timestamp: u64) {
+        self.tab_activity.retain(|tab_inner_id, _| tabs.iter().any(|tab| tab.tab_inner_id == *tab_inner_id));
+        for tab in tabs.iter() {
+            let activity = self.tab_activity.entry(tab.tab_inner_id).or_insert(TabActivity {
+                first_seen: elapsed_time,
+                last_active: elapsed_time,
+                activations: 0,
+                was_active: false,
+            });
+            if tab.is_active {
+                if !activity.was_active {
+                    activity.activations += 1;
+                }
+                activity.last_active = elapsed_time;
+            }
+            activity.was_active = tab.is_active;
+        }
+
+        self.tab_scores.clear();
+        for tab in tabs.iter() {
+            let pattern = pattern_manager.likelihood(&tab.url, timestamp);
+            let score = self.score(tab, elapsed_time, pattern);
+            self.tab_scores.insert(tab.tab_inner_id, score);
+        }
+    }
+
+    pub fn score_of(&self, tab_inner_id: i32) -> ReuseScore {
+        self.tab_scores.get(&tab_inner_id).copied().unwrap_or_default()
+    }
+
+    /// Expected reuse divided by the memory given back, the lowest is the cheapest victim. A
+    /// negative score, from a site sacrificed first, is multiplied instead so the biggest of those
+    /// tabs still goes first.
+    pub fn score_per_kb(&self, tab: &TabProcessInfo) -> f64 {
+        let score = self.score_of(tab.tab_inner_id).score;
+        let rss = tab.tab_rss.max(1) as f64;
+        if score < 0.0 { score * rss } else { score / rss }
+    }
+
+    fn score(&self, tab: &TabProcessInfo, elapsed_time: u64, pattern: f64) -> ReuseScore {
+        let Some(activity) = self.tab_activity.get(&tab.tab_inner_id) else {
+            return ReuseScore::default();
+        };
+        let config = &self.config;
+        let half_life = |time: u64, half_life: u64| {
+            if half_life == 0 { 0.0 } else { 0.5f64.powf(time as f64 / half_life as f64) }
+        };
+
+        let recency = half_life(elapsed_time - activity.last_active, config.recency_half_life);
+        // activations per hour, a tab younger than an hour is judged as if it were an hour old
+        let age_hours = ((elapsed_time - activity.first_seen) as f64 / HOUR).max(1.0);
+        let rate = activity.activations as f64 / age_hours;
+        let frequency = rate / (rate + 1.0);
+        let age = half_life(elapsed_time - activity.first_seen, config.age_half_life);
+        // a big page takes long to load again and a pinned one is expected to stay around
+        let reload_cost = if tab.pinned {
+            1.0
+        } else if config.reload_cost_rss > 0 {
+            (tab.tab_rss as f64 / config.reload_cost_rss as f64).min(1.0)
+        } else {
+            0.0
+        };
+        let host = url_host(&tab.url);
+        let priority = config
+            .priority
+            .iter()
+            .find(|site| matches_pattern(&site.pattern, host))
+            .map_or(0.0, |site| site.priority);
+
+        let score = config.recency_weight * recency
+            + config.frequency_weight * frequency
+            + config.age_weight * age
+            + config.reload_cost_weight * reload_cost
+            + config.priority_weight * priority
+            + config.pattern_weight * pattern;
+        ReuseScore { score, recency, frequency, age, reload_cost, priority, pattern }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::SitePriority;
+
+    const DAY: u64 = 86400;
+
+    fn tab(tab_inner_id: i32, url: &str, tab_rss: i32) -> TabProcessInfo {
+        TabProcessInfo { tab_inner_id, tab_process_id: 1000 + tab_inner_id, url: url.to_string(), tab_rss, ..Default::default() }
+    }
+
+    fn manager(activity: &[(i32, u64, u64, u32)]) -> ReuseManager {
+        let mut manager = ReuseManager::new(&ReuseConfig::default());
+        for &(tab_inner_id, first_seen, last_active, activations) in activity {
+            manager.tab_activity.insert(tab_inner_id, TabActivity { first_seen, last_active, activations, was_active: false });
+        }
+        manager
+    }
+
+    #[test]
+    fn unknown_tab_scores_zero() {
+        let score = manager(&[]).score(&tab(1, "https://a.com", 100), 0, 0.0);
+        assert_eq!(score.score, 0.0);
+    }
+
+    #[test]
+    fn parts_of_the_score() {
+        // seen two days ago, active one recency half life ago, 48 activations in 48 hours
+        let now = 2 * DAY;
+        let manager = manager(&[(1, 0, now - 1800, 48)]);
+        let score = manager.score(&tab(1, "https://a.com", 250_000), now, 0.5);
+        assert!((score.recency - 0.5).abs() < 1e-9);
+        assert!((score.frequency - 0.5).abs() < 1e-9);
+        assert!((score.age - 0.25).abs() < 1e-9);
+        assert!((score.reload_cost - 0.5).abs() < 1e-9);
+        assert_eq!(score.priority, 0.0);
+        assert_eq!(score.pattern, 0.5);
+        // 1.0 * 0.5 + 1.0 * 0.5 + 0.5 * 0.25 + 0.5 * 0.5 + 1.0 * 0.5
+        assert!((score.score - 1.875).abs() < 1e-9);
+    }
+
+    #[test]
+    fn young_tab_is_judged_as_an_hour_old() {
+        let manager = manager(&[(1, 0, 60, 1)]);
+        let score = manager.score(&tab(1, "https://a.com", 0), 60, 0.0);
+        assert!((score.frequency - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pinned_tab_is_the_costliest_to_reload() {
+        let manager = manager(&[(1, 0, 0, 0)]);
+        let mut pinned = tab(1, "https://a.com", 10);
+        pinned.pinned = true;
+        assert_eq!(manager.score(&pinned, 0, 0.0).reload_cost, 1.0);
+        assert_eq!(manager.score(&tab(1, "https://a.com", 2_000_000), 0, 0.0).reload_cost, 1.0);
+    }
+
+    #[test]
+    fn first_matching_site_priority_wins() {
+        let mut manager = manager(&[(1, 0, 0, 0), (2, 0, 0, 0)]);
+        manager.config.priority = vec![
+            SitePriority { pattern: "*.youtube.com".to_string(), priority: -1.0 },
+            SitePriority { pattern: "*".to_string(), priority: 0.2 },
+        ];
+        assert_eq!(manager.score(&tab(1, "https://www.youtube.com/watch", 0), 0, 0.0).priority, -1.0);
+        assert_eq!(manager.score(&tab(2, "https://docs.rs", 0), 0, 0.0).priority, 0.2);
+    }
+
+    #[test]
+    fn score_per_kb_favours_big_tabs_on_equal_score() {
+        let mut manager = manager(&[]);
+        manager.tab_scores.insert(1, ReuseScore { score: 1.0, ..Default::default() });
+        manager.tab_scores.insert(2, ReuseScore { score: 1.0, ..Default::default() });
+        assert!(manager.score_per_kb(&tab(1, "", 1000)) < manager.score_per_kb(&tab(2, "", 10)));
+        // a tab without rss does not divide by zero
+        assert_eq!(manager.score_per_kb(&tab(1, "", 0)), 1.0);
+    }
+
+    #[test]
+    fn score_per_kb_favours_big_tabs_on_negative_score() {
+        let mut manager = manager(&[]);
+        manager.tab_scores.insert(1, ReuseScore { score: -0.5, ..Default::default() });
+        manager.tab_scores.insert(2, ReuseScore { score: -0.5, ..Default::default() });
+        manager.tab_scores.insert(3, ReuseScore { score: 0.1, ..Default::default() });
+        assert!(manager.score_per_kb(&tab(1, "", 1000)) < manager.score_per_kb(&tab(2, "", 10)));
+        // a sacrificed site goes before any other tab, whatever their sizes
+        assert!(manager.score_per_kb(&tab(2, "", 10)) < manager.score_per_kb(&tab(3, "", 1_000_000)));
+    }
+}
@@ -1,8 +1,11 @@
 use std::thread::JoinHandle;
-use ctrlc;
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+use std::os::unix::process::CommandExt;
 use std::process::{Command, Child};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use log::{error, info};
 
 pub struct ServerManager {
     pub threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
@@ -19,11 +22,24 @@ impl ServerManager {
         }
     }
 
-    pub fn start_python_script(&self, script_name: &str) -> std::io::Result<Child> {
-        Command::new("python3")
-            .arg(script_name)
-            .spawn()
+    /// Starts a server script, `listen_fd` is a socket from systemd socket activation the
+    /// script serves instead of binding its own port, it is passed as LISTEN_FD.
+    pub fn start_python_script(&self, script_name: &str, listen_fd: Option<RawFd>) -> std::io::Result<Child> {
+        let mut command = Command::new("python3");
+        command.arg(script_name);
+        if let Some(fd) = listen_fd {
+            command.env("LISTEN_FD", fd.to_string());
+            // SAFETY: only calls fcntl, which is async-signal-safe, between fork and exec
+            unsafe {
+                command.pre_exec(move || {
+                    libc::fcntl(fd, libc::F_SETFD, 0);
+                    Ok(())
+                });
+            }
+        }
+        command.spawn()
     }
+
     // clear thread and process call by thread
     fn clean_up_internal(
         threads: &Arc<Mutex<Vec<std::thread::JoinHandle<()>>>>,
@@ -39,11 +55,11 @@ impl ServerManager {
         {
             let mut threads = threads.lock().unwrap();
             for thread in threads.iter() {
-                let _ = thread.thread().unpark(); // stop the thread
+                thread.thread().unpark(); // stop the thread
             }
 
             for thread in threads.drain(..) {
-                let _ = thread.join().unwrap_or_else(|_| eprintln!("Failed to join a thread"));
+                thread.join().unwrap_or_else(|_| error!("Failed to join a thread"));
             }
         }
 
@@ -52,15 +68,16 @@ impl ServerManager {
             let mut child_processes = child_processes.lock().unwrap();
             for mut child in child_processes.drain(..) {
                 let _ = child.kill();
-                eprintln!("Killed child process: {:?}", child.id());
+                info!("Killed child process: {:?}", child.id());
             }
         }
     }
 
+    #[allow(dead_code)]
     pub fn clean_up(&self) {
-        println!("Cleaning up threads and processes...");
+        info!("Cleaning up threads and processes...");
         Self::clean_up_internal(&self.threads, &self.stop_signal, &self.child_processes);
-        println!("Cleanup completed.");
+        info!("Cleanup completed.");
     }
 
     pub fn set_panic_hook(&self) {
@@ -70,34 +87,41 @@ impl ServerManager {
         let child_processes = Arc::clone(&self.child_processes);
     
         std::panic::set_hook(Box::new(move |info| {
-            println!("Panic occurred: {:?}", info);
+            error!("Panic occurred: {:?}", info);
             ServerManager::clean_up_internal(&threads, &stop_signal, &child_processes);
-            println!("Cleanup completed after panic.");
+            info!("Cleanup completed after panic.");
         }));
     }
 
-    pub fn run_server_thread(&self) -> std::io::Result<()> {
-        let tab_info_server_thread = {
+    /// `listen_fds` are the sockets passed by systemd, named "ingestion" and "metrics".
+    /// The tab info server is left out when the extension channel takes its place, and the
+    /// metrics server when the manager runs as a native messaging host.
+    pub fn run_server_thread(&self, listen_fds: &HashMap<String, RawFd>, tab_info_server: bool, metrics_server: bool) -> std::io::Result<()> {
+        let tab_info_server_thread = if tab_info_server {
             let threads = self.threads.clone();
-            let child = self.start_python_script("../tab_info_server/server.py")?;
+            let child = self.start_python_script("../tab_info_server/server.py", listen_fds.get("ingestion").copied())?;
             self.child_processes.lock().unwrap().push(child);
-            thread::spawn(move || {
+            Some(thread::spawn(move || {
                 threads.lock().unwrap().retain(|t| t.thread().id() != thread::current().id());
-            })
+            }))
+        } else {
+            None
         };
 
-        let grafana_server_thread = {
+        let grafana_server_thread = if metrics_server {
             let threads = self.threads.clone();
-            let child = self.start_python_script("../grafana/server.py")?;
+            let child = self.start_python_script("../grafana/server.py", listen_fds.get("metrics").copied())?;
             self.child_processes.lock().unwrap().push(child);
-            thread::spawn(move || {
+            Some(thread::spawn(move || {
                 threads.lock().unwrap().retain(|t| t.thread().id() != thread::current().id());
-            })
+            }))
+        } else {
+            None
         };
 
         let mut threads = self.threads.lock().unwrap();
-        threads.push(tab_info_server_thread);
-        threads.push(grafana_server_thread);
+        threads.extend(tab_info_server_thread);
+        threads.extend(grafana_server_thread);
         Ok(())
     }
 
@@ -108,16 +132,16 @@ impl ServerManager {
 
         ctrlc::set_handler(move || {
             // Handle cleanup when Ctrl+C is pressed
-            println!("Received termination signal. Cleaning up...");
+            info!("Received termination signal. Cleaning up...");
             ServerManager::clean_up_internal(&threads, &stop_signal, &child_processes);
-            println!("Cleanup completed after termination signal.");
+            info!("Cleanup completed after termination signal.");
         }).expect("Failed to set Ctrl+C handler");
     }
 
     pub fn cleanup_thread(&self) {
         let mut threads = self.threads.lock().unwrap();
         for thread in threads.drain(..) {
-            thread.join().unwrap_or_else(|_| eprintln!("Failed to join a thread"));
+            thread.join().unwrap_or_else(|_| error!("Failed to join a thread"));
         }
     }
 
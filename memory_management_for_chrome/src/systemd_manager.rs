@@ -0,0 +1,232 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// First file descriptor passed by systemd socket activation.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+pub const SERVICE_NAME: &str = "chrome-memory-manager";
+
+/// Integration with systemd: sd_notify, watchdog and socket activation.
+/// Every call is a no-op when the process is not started by systemd.
+pub struct SystemdManager {
+    notify_socket: Option<String>,
+    pub watchdog_interval: Option<Duration>,
+    pub listen_fds: HashMap<String, RawFd>, // FileDescriptorName -> fd
+}
+
+impl SystemdManager {
+    pub fn new() -> Self {
+        let notify_socket = env::var("NOTIFY_SOCKET").ok();
+        let watchdog_interval = env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| for_this_process("WATCHDOG_PID"))
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .map(Duration::from_micros);
+        let listen_fds = take_listen_fds();
+        // children must not see the variables meant for this process
+        for name in ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID", "LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            env::remove_var(name);
+        }
+        Self { notify_socket, watchdog_interval, listen_fds }
+    }
+
+    /// Sends a state string such as "READY=1" to the service manager.
+    pub fn notify(&self, state: &str) {
+        let Some(path) = self.notify_socket.as_ref() else {
+            return;
+        };
+        let result = UnixDatagram::unbound().and_then(|socket| {
+            // a leading '@' means an abstract socket
+            let address = match path.strip_prefix('@') {
+                Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+                None => SocketAddr::from_pathname(path)?,
+            };
+            socket.send_to_addr(state.as_bytes(), &address)
+        });
+        match result {
+            Ok(_) => debug!("sd_notify: {}", state.replace('\n', " ")),
+            Err(err) => warn!("Failed to notify systemd ({}): {}", state, err),
+        }
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.notify(&format!("READY=1\nSTATUS={}", status));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+
+    pub fn watchdog(&self) {
+        if self.watchdog_interval.is_some() {
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+}
+
+fn for_this_process(variable: &str) -> bool {
+    env::var(variable)
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id())
+}
+
+/// Collects the sockets passed with LISTEN_FDS, named after LISTEN_FDNAMES.
+/// They are marked close-on-exec, `ServerManager` hands each one only to the child that serves it.
+fn take_listen_fds() -> HashMap<String, RawFd> {
+    if env::var_os("LISTEN_PID").is_none() || !for_this_process("LISTEN_PID") {
+        return HashMap::new();
+    }
+    let listen_fds = name_listen_fds(env::var("LISTEN_FDS").ok().as_deref(), env::var("LISTEN_FDNAMES").ok().as_deref());
+    for fd in listen_fds.values() {
+        // SAFETY: fcntl on a descriptor systemd handed to this process
+        unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    listen_fds
+}
+
+/// Descriptors from SD_LISTEN_FDS_START on, named after the colon separated `names`.
+fn name_listen_fds(count: Option<&str>, names: Option<&str>) -> HashMap<String, RawFd> {
+    let count: RawFd = count.and_then(|count| count.parse().ok()).unwrap_or(0);
+    let names: Vec<&str> = names.map(|names| names.split(':').collect()).unwrap_or_default();
+    (0..count)
+        .map(|index| {
+            let name = names.get(index as usize).map_or_else(|| format!("fd{}", index), |name| name.to_string());
+            (name, SD_LISTEN_FDS_START + index)
+        })
+        .collect()
+}
+
+/// Writes the user units for the manager and its two sockets, returns the written paths.
+/// With `install` false the units are printed instead.
+pub fn generate_units(install: bool, reflush_time: u64) -> io::Result<Vec<PathBuf>> {
+    let units = units(&env::current_exe()?, &env::current_dir()?, reflush_time);
+
+    if !install {
+        for (name, content) in units.iter() {
+            println!("# {}\n{}", name, content);
+        }
+        return Ok(Vec::new());
+    }
+
+    let home = env::var_os("HOME").ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    let unit_directory = Path::new(&home).join(".config/systemd/user");
+    fs::create_dir_all(&unit_directory)?;
+    let mut written = Vec::new();
+    for (name, content) in units.iter() {
+        let path = unit_directory.join(name);
+        fs::write(&path, content)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Names and contents of the service and socket units.
+fn units(executable: &Path, working_directory: &Path, reflush_time: u64) -> [(String, String); 3] {
+    // the loop pings the watchdog once per tick
+    let watchdog_sec = (reflush_time * 4).max(30);
+
+    let service = format!(
+        "[Unit]
+Description=Google Chrome Memory Manager
+Requires={name}-ingestion.socket {name}-metrics.socket
+After={name}-ingestion.socket {name}-metrics.socket
+
+[Service]
+Type=notify
+NotifyAccess=main
+WorkingDirectory={working_directory}
+ExecStart={executable}
+WatchdogSec={watchdog_sec}
+Restart=on-failure
+TimeoutStopSec=15
+
+[Install]
+WantedBy=default.target
+",
+        name = SERVICE_NAME,
+        working_directory = working_directory.display(),
+        executable = executable.display(),
+        watchdog_sec = watchdog_sec,
+    );
+    let socket = |description: &str, listen: &str, fd_name: &str| {
+        format!(
+            "[Unit]
+Description=Google Chrome Memory Manager {description}
+
+[Socket]
+ListenStream={listen}
+FileDescriptorName={fd_name}
+Service={name}.service
+
+[Install]
+WantedBy=sockets.target
+",
+            description = description,
+            listen = listen,
+            fd_name = fd_name,
+            name = SERVICE_NAME,
+        )
+    };
+    // the metrics server also serves the tab history, it stays on the loopback like the ingestion one
+    [
+        (format!("{}.service", SERVICE_NAME), service),
+        (format!("{}-ingestion.socket", SERVICE_NAME), socket("tab ingestion socket", "127.0.0.1:8080", "ingestion")),
+        (format!("{}-metrics.socket", SERVICE_NAME), socket("metrics socket", "127.0.0.1:5000", "metrics")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_run_the_manager_and_listen_on_the_loopback() {
+        let [(service_name, service), (_, ingestion), (metrics_name, metrics)] =
+            units(Path::new("/opt/manager/bin"), Path::new("/opt/manager"), 5);
+        assert_eq!(service_name, "chrome-memory-manager.service");
+        assert!(service.contains("ExecStart=/opt/manager/bin\n"));
+        assert!(service.contains("WorkingDirectory=/opt/manager\n"));
+        // never below 30 seconds, whatever the tick
+        assert!(service.contains("WatchdogSec=30\n"));
+        assert!(units(Path::new("/bin"), Path::new("/"), 20)[0].1.contains("WatchdogSec=80\n"));
+        assert!(ingestion.contains("ListenStream=127.0.0.1:8080\nFileDescriptorName=ingestion\n"));
+        assert_eq!(metrics_name, "chrome-memory-manager-metrics.socket");
+        assert!(metrics.contains("ListenStream=127.0.0.1:5000\nFileDescriptorName=metrics\n"));
+    }
+
+    #[test]
+    fn listen_fds_are_named_in_order() {
+        let listen_fds = name_listen_fds(Some("3"), Some("ingestion:metrics"));
+        assert_eq!(listen_fds.len(), 3);
+        assert_eq!(listen_fds["ingestion"], 3);
+        assert_eq!(listen_fds["metrics"], 4);
+        assert_eq!(listen_fds["fd2"], 5);
+        assert!(name_listen_fds(None, Some("ingestion")).is_empty());
+        assert!(name_listen_fds(Some("many"), None).is_empty());
+    }
+
+    #[test]
+    fn variables_for_another_process_are_ignored() {
+        // a variable of its own, the other tests do not read it
+        let variable = "CHROME_MEMORY_MANAGER_TEST_PID";
+        assert!(for_this_process(variable));
+        env::set_var(variable, std::process::id().to_string());
+        assert!(for_this_process(variable));
+        env::set_var(variable, "1");
+        assert!(!for_this_process(variable));
+        env::remove_var(variable);
+    }
+}
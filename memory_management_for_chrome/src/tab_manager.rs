@@ -4,12 +4,143 @@ use std::io::{BufRead, BufReader, Read, Write};
 use json::JsonValue;
 use std::path::Path;
 use std::io;
+use log::{debug, warn};
+
+use crate::history_manager::unix_now;
+use crate::manager_error::{self, ManagerError};
+use crate::memory_manager::MemoryManager;
+use crate::process_handle::{process_start_time, renderer_client_id, ProcessKey};
+use crate::text_width;
+
+/// Fields of a tab reported by the browser extension.
+#[derive(Clone)]
+pub struct TabInfo {
+    pub chrome_tab_id: i32, // -1 from an extension that does not send it
+    pub tab_name: String,
+    pub is_active: bool,
+    pub url: String,
+    pub pinned: bool,
+    pub window_id: i32,
+    pub index: i32, // position of the tab in its window
+    pub group_id: i32, // -1 outside of any tab group
+    pub last_interaction: Option<u64>, // unix second of the last input in the page or activation of the tab
+    pub audible: bool,
+    pub muted: bool,
+    pub media_playing: bool, // audio or video playing in the page, muted or not
+}
+
+/// Where a tab stands relative to what the user sees.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Visibility {
+    Foreground, // active tab of the window the user looked at last
+    Visible,    // active tab of another window that is not minimized
+    Minimized,  // active tab of a minimized window
+    #[default]
+    Background, // any tab that is not the active one of its window
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Foreground => "foreground",
+            Visibility::Visible => "visible",
+            Visibility::Minimized => "minimized",
+            Visibility::Background => "background",
+        }
+    }
+}
+
+/// A browser window as reported by `chrome.windows.getAll`.
+pub struct WindowInfo {
+    pub state: String, // normal, minimized, maximized, fullscreen or locked-fullscreen
+    pub focused: bool,
+}
+
+/// A tab group as reported by `chrome.tabGroups.query`.
+pub struct GroupInfo {
+    pub title: String,
+    pub collapsed: bool,
+}
+
+/// Browser wide signals sent along with the tabs, missing from older extensions.
+pub struct BrowserSignals {
+    pub windows: HashMap<i32, WindowInfo>, // window id -> window
+    pub groups: HashMap<i32, GroupInfo>,   // group id -> group
+    pub last_focused_window_id: Option<i32>,
+    pub idle_state: String,        // active, idle or locked
+    pub idle_since: Option<u64>,   // unix second the system went idle or locked
+}
+
+impl Default for BrowserSignals {
+    fn default() -> Self {
+        Self { windows: HashMap::new(), groups: HashMap::new(), last_focused_window_id: None, idle_state: "active".to_string(), idle_since: None }
+    }
+}
+
+impl BrowserSignals {
+    /// Without window information from the extension every active tab is in the foreground.
+    pub fn visibility(&self, tab: &TabInfo) -> Visibility {
+        if !tab.is_active {
+            return Visibility::Background;
+        }
+        let window = self.windows.get(&tab.window_id);
+        if window.is_some_and(|window| window.state == "minimized") {
+            return Visibility::Minimized;
+        }
+        let foreground = match self.last_focused_window_id {
+            Some(window_id) => window_id == tab.window_id,
+            None => window.is_none_or(|window| window.focused),
+        };
+        if foreground { Visibility::Foreground } else { Visibility::Visible }
+    }
+}
+
+/// A tab joined with its renderer process, `tab_process_id` is -1 when the process is gone.
+#[derive(Clone, Default)]
+pub struct TabProcessInfo {
+    pub tab_inner_id: i32,
+    pub chrome_tab_id: i32, // what commands sent to the extension refer to
+    pub tab_name: String,
+    pub tab_process_id: i32,
+    pub tab_rss: i32,
+    pub is_active: bool,
+    pub url: String,
+    pub pinned: bool,
+    pub start_time: u64, // of the renderer process, 0 when it is gone
+    pub window_id: i32,
+    pub index: i32,
+    pub visibility: Visibility,
+    pub group_id: i32,
+    pub group_title: String, // empty outside of a group or for an untitled group
+    pub group_collapsed: bool,
+    pub audible: bool,
+    pub muted: bool,
+    pub media_playing: bool,
+    pub in_use: bool,   // in front of a present user, or playing media
+    pub last_used: u64, // unix second the tab was last in use, first seen if never
+}
+
+impl TabProcessInfo {
+    /// Key of the per-process state, it changes when the tab gets a new renderer.
+    pub fn process_key(&self) -> ProcessKey {
+        ProcessKey { tab_inner_id: self.tab_inner_id, pid: self.tab_process_id, start_time: self.start_time }
+    }
+
+    /// Seconds since the tab was last in use.
+    pub fn idle_time(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.last_used)
+    }
+}
 
 pub struct TabManager {
-    pub tab_info_map: HashMap<i32, (String, bool)>, // tab_inner_id tab_name is_active
+    pub tab_info_map: HashMap<i32, TabInfo>, // tab_inner_id -> tab info
     pub pid_rss_vector: Vec<(i32, i32)>,
     pub tab_process_info_map: HashMap<String, (i32, i32)>,
-    pub tabid_tabname_tabpid_isActive_map: Vec<(i32, String, i32, i32, bool)>,
+    pub pid_start_time_map: HashMap<i32, u64>, // read together with the cmdline of each renderer
+    pub tabid_tabname_tabpid_isActive_map: Vec<TabProcessInfo>,
+    pub browser_signals: BrowserSignals,
+    pub tab_last_used: HashMap<i32, u64>, // tab_inner_id -> unix second, kept across ticks
+    pub chrome_tab_ids: HashMap<i32, i32>, // Chrome tab id -> tab_inner_id
 }
 
 impl TabManager {
@@ -18,39 +149,123 @@ impl TabManager {
             tab_info_map: HashMap::new(),
             pid_rss_vector: Vec::new(),
             tab_process_info_map: HashMap::new(),
+            pid_start_time_map: HashMap::new(),
             tabid_tabname_tabpid_isActive_map: Vec::new(),
+            browser_signals: BrowserSignals::default(),
+            tab_last_used: HashMap::new(),
+            chrome_tab_ids: HashMap::new(),
         }
     }
 
+    /// Reads the tab snapshot and the process source and joins them into `tabid_tabname_tabpid_isActive_map`.
+    /// Without `log_path` the tabs are the ones kept up to date by the events of the extension channel.
+    pub fn observe(&mut self, log_path: Option<&Path>, chrome_info_path: &Path) -> manager_error::Result<()> {
+        if let Some(log_path) = log_path {
+            self.build_tab_info_map(log_path)?;
+        }
+        self.get_pid_from_chrome_info(chrome_info_path)?;
+        self.build_tab_process_info_map();
+        self.build_tabid_tabname_tabpid_isActive_map();
+        self.print_tab_process_info_map();
+        Ok(())
+    }
+
     /// Builds the `tab_info_map` by parsing a JSON log file.
-    pub fn build_tab_info_map(&mut self, log_path: &Path) -> std::io::Result<()> {
-        let log_file = File::open(log_path).unwrap_or_else(|err| {
-            eprintln!("Failed to open file: {}", err);
-            panic!("Cannot proceed without the file log.json! file_path: {}", log_path.display());
-        });
+    pub fn build_tab_info_map(&mut self, log_path: &Path) -> manager_error::Result<()> {
+        let log_file = File::open(log_path).map_err(|err| ManagerError::io(log_path, err))?;
         let mut reader = BufReader::new(log_file);
         let mut content = String::new();
 
         // println!("Find log.json and open successfully!");
-        reader.read_to_string(&mut content)?;
-        let parsed = json::parse(&content).expect("Failed to parse JSON");
-        self.traverse_json(&parsed);
+        reader.read_to_string(&mut content).map_err(|err| ManagerError::io(log_path, err))?;
+        let parsed = json::parse(&content).map_err(|err| ManagerError::Json {
+            path: log_path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        self.apply_snapshot(&parsed)
+    }
+
+    /// Replaces every tab and the browser signals with a full tab list from the extension.
+    fn apply_snapshot(&mut self, parsed: &JsonValue) -> manager_error::Result<()> {
+        self.tab_info_map.clear();
+        self.chrome_tab_ids.clear();
+        self.browser_signals = parse_browser_signals(parsed);
+        self.traverse_json(parsed);
+        Ok(())
+    }
+
+    /// Applies one event of the extension channel: a full snapshot, a tab created, updated,
+    /// activated or removed, the activity of a tab, or new browser signals.
+    pub fn apply_event(&mut self, event: &JsonValue) -> manager_error::Result<()> {
+        let tab_id = event["tabId"].as_i32().unwrap_or(-1);
+        match event["type"].as_str().unwrap_or("") {
+            "snapshot" => self.apply_snapshot(event)?,
+            "created" | "updated" => {
+                if let JsonValue::Object(obj) = &event["tab"] {
+                    let (tab_inner_id, tab_info) = parse_tab(obj)?;
+                    self.insert_tab(tab_inner_id, tab_info);
+                }
+            }
+            "activated" => {
+                let window_id = event["windowId"].as_i32().unwrap_or(-1);
+                let activated_at = event["time"].as_u64().map(|ms| ms / 1000);
+                for tab_info in self.tab_info_map.values_mut().filter(|tab_info| tab_info.window_id == window_id) {
+                    tab_info.is_active = tab_info.chrome_tab_id == tab_id;
+                    if tab_info.is_active {
+                        tab_info.last_interaction = tab_info.last_interaction.max(activated_at);
+                    }
+                }
+            }
+            "removed" => {
+                // another tab may have taken over the renderer since
+                if let Some(tab_inner_id) = self.chrome_tab_ids.remove(&tab_id) {
+                    if self.tab_info_map.get(&tab_inner_id).is_some_and(|tab_info| tab_info.chrome_tab_id == tab_id) {
+                        self.tab_info_map.remove(&tab_inner_id);
+                    }
+                }
+            }
+            "activity" => {
+                let Some(tab_info) = self.chrome_tab_ids.get(&tab_id).and_then(|tab_inner_id| self.tab_info_map.get_mut(tab_inner_id)) else {
+                    return Ok(());
+                };
+                let last_interaction = event["lastInteraction"].as_u64().map(|ms| ms / 1000);
+                tab_info.last_interaction = tab_info.last_interaction.max(last_interaction);
+                if let Some(media_playing) = event["mediaPlaying"].as_bool() {
+                    tab_info.media_playing = media_playing;
+                }
+            }
+            "signals" => self.browser_signals = parse_browser_signals(event),
+            other => debug!("Ignoring channel event {:?}", other),
+        }
         Ok(())
     }
 
-    /// Traverses a JSON structure to populate the `tab_info_map`.
+    /// Adds or replaces a tab, dropping the entry it had under its previous renderer.
+    fn insert_tab(&mut self, tab_inner_id: i32, tab_info: TabInfo) {
+        if tab_info.chrome_tab_id != -1 {
+            if let Some(previous) = self.chrome_tab_ids.insert(tab_info.chrome_tab_id, tab_inner_id) {
+                if previous != tab_inner_id && self.tab_info_map.get(&previous).is_some_and(|info| info.chrome_tab_id == tab_info.chrome_tab_id) {
+                    self.tab_info_map.remove(&previous);
+                }
+            }
+        }
+        self.tab_info_map.insert(tab_inner_id, tab_info);
+    }
+
+    /// Traverses a JSON structure to populate the `tab_info_map`. A malformed tab is skipped,
+    /// the rest of the snapshot is still used.
     fn traverse_json(&mut self, value: &JsonValue) {
         match value {
             JsonValue::Object(obj) => {
-                for (key, value) in obj.iter() {
-                    if key == "pid" {
-                        let tab_inner_id = value.as_i32().unwrap();
-                        let tab_name = obj.get("title").unwrap().as_str().unwrap();
-                        let is_active = obj.get("active").unwrap().as_bool().unwrap();
-                        self.tab_info_map.insert(tab_inner_id, (tab_name.to_string(), is_active));
-                    } else {
-                        self.traverse_json(value);
+                if obj.get("pid").is_some() {
+                    match parse_tab(obj) {
+                        Ok((tab_inner_id, tab_info)) => self.insert_tab(tab_inner_id, tab_info),
+                        Err(err) => warn!("Skipping a tab of the snapshot: {}", err),
                     }
+                    return;
+                }
+                for (_, value) in obj.iter() {
+                    self.traverse_json(value);
                 }
             }
             JsonValue::Array(arr) => {
@@ -64,16 +279,13 @@ impl TabManager {
 
     /// Populates the `pid_rss_vector` by reading process info from `/proc/chrome_info`.
     /// /proc/chrome_info is crate by kernel module
-    pub fn get_pid_from_chrome_info(&mut self, chrome_info_path: &Path) -> std::io::Result<()> {
-        let chrome_info_file = File::open(chrome_info_path).unwrap_or_else(|err| {
-            eprintln!("Failed to open file: {}", err);
-            panic!("Cannot proceed without the file! Please load the kernel module first");
-        });
+    pub fn get_pid_from_chrome_info(&mut self, chrome_info_path: &Path) -> manager_error::Result<()> {
+        let chrome_info_file = File::open(chrome_info_path).map_err(|err| ManagerError::io(chrome_info_path, err))?;
 
         // println!("Find chrome_info and open successfully!");
         
         for line in BufReader::new(chrome_info_file).lines() {
-            let line = line?;
+            let line = line.map_err(|err| ManagerError::io(chrome_info_path, err))?;
             let mut pid: i32 = -1; 
             let mut rss: i32 = 0; 
             // parse to find PID
@@ -103,60 +315,52 @@ impl TabManager {
         Ok(())
     }
 
+    /// Total rss of every Chrome process listed by the kernel module, in KB.
+    pub fn browser_rss(&self) -> i32 {
+        self.pid_rss_vector.iter().map(|(_, rss)| rss).sum()
+    }
+
     /// Builds the `tab_process_info_map` by associating PIDs with tab IDs.
     pub fn build_tab_process_info_map(&mut self) {
         for pid_rss in self.pid_rss_vector.iter() {
             let cmdline_path = format!("/proc/{}/cmdline", pid_rss.0);
-            if let Ok(cmdline) = fs::read_to_string(&cmdline_path) {
-                let key = "--renderer-client-id=";
-                if let Some(pos) = cmdline.find(key) {
-                    let end_pos = cmdline[pos + key.len()..].find(' ').unwrap_or(cmdline.len());
-                    let tab_inner_id = &cmdline[pos + key.len()..pos + key.len() + end_pos];
-                    self.tab_process_info_map.insert(tab_inner_id.to_string(), *pid_rss);
-                }
-            }
+            let Ok(cmdline) = fs::read_to_string(&cmdline_path) else {
+                continue;
+            };
+            let (Some(tab_inner_id), Some(start_time)) = (renderer_client_id(&cmdline), process_start_time(pid_rss.0)) else {
+                continue;
+            };
+            self.tab_process_info_map.insert(tab_inner_id.to_string(), *pid_rss);
+            self.pid_start_time_map.insert(pid_rss.0, start_time);
         }
     }
 
     /// Prints the tab process info in a formatted way.
     pub fn print_tab_process_info_map(&self) {
-        let is_chinese = |c: char| {
-            (c >= '\u{4E00}' && c <= '\u{9FFF}') || // CJK Unified Ideographs
-            (c >= '\u{3400}' && c <= '\u{4DBF}') || // CJK Unified Ideographs Extension A
-            (c >= '\u{20000}' && c <= '\u{2A6DF}') || // CJK Unified Ideographs Extension B
-            (c >= '\u{2A700}' && c <= '\u{2B73F}') || // CJK Unified Ideographs Extension C
-            (c >= '\u{2B740}' && c <= '\u{2B81F}') || // CJK Unified Ideographs Extension D
-            (c >= '\u{2B820}' && c <= '\u{2CEAF}') || // CJK Unified Ideographs Extension E
-            (c >= '\u{F900}' && c <= '\u{FAFF}') ||   // CJK Compatibility Ideographs
-            (c >= '\u{2F800}' && c <= '\u{2FA1F}')    // CJK Compatibility Ideographs Supplement
-        };
-        
-        let count_chinese_characters = |input: &str| input.chars().filter(|&c| is_chinese(c)).count();
-
-        println!("print_tab_process_info_map:");
-        for (tab_inner_id, tab_name, tab_process_id, tab_rss, is_active) in self.tabid_tabname_tabpid_isActive_map.iter() {
-            let chinese_count = count_chinese_characters(tab_name);
-            let tab_name_offset = 30 - chinese_count;
-            println!(
-                "tab_inner_id: {:<5} tab_name: {:<tab_name_offset$} tab_process_id: {:>5} tab_rss: {:>5} is_active: {:>5}", 
-                tab_inner_id, 
-                tab_name, 
+        debug!("print_tab_process_info_map:");
+        for TabProcessInfo { tab_inner_id, tab_name, tab_process_id, tab_rss, is_active, .. } in self.tabid_tabname_tabpid_isActive_map.iter() {
+            debug!(
+                "tab_inner_id: {:<5} tab_name: {} tab_process_id: {:>5} tab_rss: {:>5} is_active: {:>5}",
+                tab_inner_id,
+                text_width::pad(tab_name, 30),
                 tab_process_id,
                 tab_rss,
-                is_active,
-                tab_name_offset = tab_name_offset
+                is_active
             );
         }
     }
 
-    pub fn write_tab_process_info_to_file(&self, file_path: &str, pid_inActive_time_counter: &HashMap<i32, i32>) -> io::Result<()> {
+    pub fn write_tab_process_info_to_file(&self, file_path: &str, memory_manager: &MemoryManager) -> io::Result<()> {
         let mut file = File::create(file_path)?;
         let tab_info_map_len = self.tab_info_map.len();
         let mut counter: usize = 0;
     
         writeln!(file, "{{")?;
         writeln!(file, "\t\"tab_info_instance\": [")?;
-        for (tab_inner_id, (tab_name, is_active)) in self.tab_info_map.iter() {
+        let now = unix_now();
+        for (tab_inner_id, tab_info) in self.tab_info_map.iter() {
+            let TabInfo { tab_name, is_active, url, pinned, window_id, index, group_id, audible, muted, media_playing, .. } = tab_info;
+            let group = self.browser_signals.groups.get(group_id);
             let pid_rss: (i32, i32) = *self
                 .tab_process_info_map
                 .get(&tab_inner_id.to_string())
@@ -165,30 +369,183 @@ impl TabManager {
             counter += 1;
             writeln!(file, "\t\t{{")?;
             writeln!(file, "\t\t\t\"tab_id\": {},", tab_inner_id)?;
-            writeln!(file, "\t\t\t\"tab_name\": {},", serde_json::json!(tab_name).to_string())?;
+            writeln!(file, "\t\t\t\"tab_name\": {},", serde_json::json!(tab_name))?;
             writeln!(file, "\t\t\t\"tab_process_id\": {},", pid_rss.0)?;
             writeln!(file, "\t\t\t\"tab_rss\": {},", pid_rss.1)?;
+            writeln!(file, "\t\t\t\"tab_url\": {},", serde_json::json!(url))?;
             writeln!(file, "\t\t\t\"is_active\": {},", is_active)?;
-            writeln!(file, "\t\t\t\"inActive_time\": {}", pid_inActive_time_counter.get(&pid_rss.0).unwrap_or(&0))?;
+            writeln!(file, "\t\t\t\"pinned\": {},", pinned)?;
+            writeln!(file, "\t\t\t\"window_id\": {},", window_id)?;
+            writeln!(file, "\t\t\t\"index\": {},", index)?;
+            writeln!(file, "\t\t\t\"visibility\": \"{}\",", self.browser_signals.visibility(tab_info).as_str())?;
+            writeln!(file, "\t\t\t\"group_id\": {},", group_id)?;
+            writeln!(file, "\t\t\t\"group_title\": {},", serde_json::json!(group.map(|group| &group.title)))?;
+            writeln!(file, "\t\t\t\"group_collapsed\": {},", group.is_some_and(|group| group.collapsed))?;
+            writeln!(file, "\t\t\t\"audible\": {},", audible)?;
+            writeln!(file, "\t\t\t\"muted\": {},", muted)?;
+            writeln!(file, "\t\t\t\"media_playing\": {},", media_playing)?;
+            let idle_time = self.tab_last_used.get(tab_inner_id).map_or(0, |last_used| now.saturating_sub(*last_used));
+            writeln!(file, "\t\t\t\"inActive_time\": {},", idle_time)?;
+            writeln!(file, "\t\t\t\"tier\": \"{}\",", memory_manager.escalation_manager.tier_of(*tab_inner_id))?;
+            writeln!(file, "\t\t\t\"budget_violation\": {},", serde_json::json!(memory_manager.budget_manager.tab_budget_violation.get(tab_inner_id)))?;
+            writeln!(file, "\t\t\t\"group_violation\": {},", serde_json::json!(memory_manager.group_manager.tab_group_violation.get(tab_inner_id)))?;
+            writeln!(file, "\t\t\t\"reuse_score\": {},", memory_manager.reuse_manager.score_of(*tab_inner_id).to_json())?;
+            let leak_suspect = memory_manager.leak_manager.leak_suspects.get(tab_inner_id).map(|suspect| {
+                serde_json::json!({ "slope": suspect.slope, "r_squared": suspect.r_squared, "growth": suspect.growth })
+            });
+            writeln!(file, "\t\t\t\"leak_suspect\": {}", serde_json::json!(leak_suspect))?;
             if counter == tab_info_map_len {
                 writeln!(file, "\t\t}}")?;
             } else {
                 writeln!(file, "\t\t}},")?;
             }
         }
-        writeln!(file, "\t]")?;
+        writeln!(file, "\t],")?;
+        writeln!(file, "\t\"status\": {}", memory_manager.status())?;
         writeln!(file, "}}")?;
         Ok(())
     }
 
     pub fn build_tabid_tabname_tabpid_isActive_map(&mut self) {
+        let now = unix_now();
+        let signals = &self.browser_signals;
+        let user_present = signals.idle_state == "active";
+        self.tab_last_used.retain(|tab_inner_id, _| self.tab_info_map.contains_key(tab_inner_id));
         //contain tab_inner_id tab_name tab_process_id tab_rss
-        for (tab_inner_id, tab_name_is_active) in self.tab_info_map.iter() {
+        for (tab_inner_id, tab_info) in self.tab_info_map.iter() {
             let pid_rss: (i32, i32) = *self
                 .tab_process_info_map
                 .get(&tab_inner_id.to_string())
                 .unwrap_or(&(-1, 0));
-            self.tabid_tabname_tabpid_isActive_map.push((*tab_inner_id, tab_name_is_active.0.to_string(), pid_rss.0, pid_rss.1, tab_name_is_active.1));
+            let visibility = signals.visibility(tab_info);
+            let group = signals.groups.get(&tab_info.group_id);
+            let foreground = visibility == Visibility::Foreground;
+            let in_use = tab_info.media_playing || (tab_info.audible && !tab_info.muted) || (foreground && user_present);
+            let last_used = self.tab_last_used.entry(*tab_inner_id).or_insert(now);
+            if let Some(last_interaction) = tab_info.last_interaction {
+                *last_used = (*last_used).max(last_interaction.min(now));
+            }
+            if in_use {
+                *last_used = now;
+            } else if foreground {
+                // the user left the foreground tab when the system went idle, not when it was noticed
+                if let Some(idle_since) = signals.idle_since {
+                    *last_used = (*last_used).min(idle_since);
+                }
+            }
+            self.tabid_tabname_tabpid_isActive_map.push(TabProcessInfo {
+                tab_inner_id: *tab_inner_id,
+                chrome_tab_id: tab_info.chrome_tab_id,
+                tab_name: tab_info.tab_name.to_string(),
+                tab_process_id: pid_rss.0,
+                tab_rss: pid_rss.1,
+                is_active: tab_info.is_active,
+                url: tab_info.url.to_string(),
+                pinned: tab_info.pinned,
+                start_time: *self.pid_start_time_map.get(&pid_rss.0).unwrap_or(&0),
+                window_id: tab_info.window_id,
+                index: tab_info.index,
+                visibility,
+                group_id: tab_info.group_id,
+                group_title: group.map(|group| group.title.clone()).unwrap_or_default(),
+                group_collapsed: group.is_some_and(|group| group.collapsed),
+                audible: tab_info.audible,
+                muted: tab_info.muted,
+                media_playing: tab_info.media_playing,
+                in_use,
+                last_used: *last_used,
+            });
         }
     }
-}
\ No newline at end of file
+}
+/// Reads a tab reported by the extension, keyed by its renderer id (`pid`).
+fn parse_tab(obj: &json::object::Object) -> manager_error::Result<(i32, TabInfo)> {
+    let tab_inner_id = obj
+        .get("pid")
+        .and_then(|pid| pid.as_i32())
+        .ok_or(ManagerError::MissingField { tab_inner_id: -1, field: "pid" })?;
+    let tab_name = obj
+        .get("title")
+        .and_then(|title| title.as_str())
+        .ok_or(ManagerError::MissingField { tab_inner_id, field: "title" })?;
+    let is_active = obj
+        .get("active")
+        .and_then(|active| active.as_bool())
+        .ok_or(ManagerError::MissingField { tab_inner_id, field: "active" })?;
+    let url = obj.get("url").and_then(|url| url.as_str()).unwrap_or("");
+    let pinned = obj.get("pinned").and_then(|pinned| pinned.as_bool()).unwrap_or(false);
+    let flag = |key: &str| obj.get(key).and_then(|value| value.as_bool()).unwrap_or(false);
+    // lastAccessed is set by Chrome when the tab is activated, lastInteraction by the extension on input
+    let last_interaction = ["lastInteraction", "lastAccessed"]
+        .iter()
+        .filter_map(|key| obj.get(key).and_then(|ms| ms.as_f64()))
+        .map(|ms| ms as u64 / 1000)
+        .max();
+    let tab_info = TabInfo {
+        chrome_tab_id: obj.get("id").and_then(|id| id.as_i32()).unwrap_or(-1),
+        tab_name: tab_name.to_string(),
+        is_active,
+        url: url.to_string(),
+        pinned,
+        window_id: obj.get("windowId").and_then(|id| id.as_i32()).unwrap_or(-1),
+        index: obj.get("index").and_then(|index| index.as_i32()).unwrap_or(-1),
+        group_id: obj.get("groupId").and_then(|id| id.as_i32()).unwrap_or(-1),
+        last_interaction,
+        audible: flag("audible"),
+        muted: obj.get("mutedInfo").is_some_and(|info| info["muted"].as_bool().unwrap_or(false)),
+        media_playing: flag("mediaPlaying"),
+    };
+    Ok((tab_inner_id, tab_info))
+}
+
+/// Reads the windows, tab groups, focus and idle state sent along with the tabs.
+fn parse_browser_signals(parsed: &JsonValue) -> BrowserSignals {
+    let windows = parsed["windows"]
+        .members()
+        .filter_map(|window| {
+            let info = WindowInfo {
+                state: window["state"].as_str().unwrap_or("normal").to_string(),
+                focused: window["focused"].as_bool().unwrap_or(false),
+            };
+            Some((window["id"].as_i32()?, info))
+        })
+        .collect();
+    let groups = parsed["groups"]
+        .members()
+        .filter_map(|group| {
+            let info = GroupInfo {
+                title: group["title"].as_str().unwrap_or("").to_string(),
+                collapsed: group["collapsed"].as_bool().unwrap_or(false),
+            };
+            Some((group["id"].as_i32()?, info))
+        })
+        .collect();
+    BrowserSignals {
+        windows,
+        groups,
+        last_focused_window_id: parsed["lastFocusedWindowId"].as_i32(),
+        idle_state: parsed["idleState"].as_str().unwrap_or("active").to_string(),
+        idle_since: parsed["idleSince"].as_u64().map(|ms| ms / 1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_tab_does_not_drop_the_snapshot() {
+        let snapshot = json::parse(r#"{"tabs": [
+            {"pid": 1, "id": 10, "title": "GitHub", "active": true},
+            {"pid": 2, "id": 11, "active": false},
+            {"pid": "3", "id": 12, "title": "Docs", "active": false},
+            {"pid": 4, "id": 13, "title": "News", "active": false}
+        ]}"#).unwrap();
+        let mut manager = TabManager::new();
+        assert!(manager.apply_snapshot(&snapshot).is_ok());
+        let mut tabs: Vec<i32> = manager.tab_info_map.keys().copied().collect();
+        tabs.sort();
+        assert_eq!(tabs, vec![1, 4]);
+        assert_eq!(manager.chrome_tab_ids.get(&13), Some(&4));
+    }
+}
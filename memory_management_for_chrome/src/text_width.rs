@@ -0,0 +1,116 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const ELLIPSIS: &str = "…";
+
+/// Columns given to a tab title inside a log line or a command output line.
+pub const TITLE_WIDTH: usize = 60;
+
+/// Terminal columns taken by one grapheme cluster.
+///
+/// East Asian wide and fullwidth characters and emoji take two columns, combining marks and
+/// joiners none of their own. A cluster is drawn as one glyph, so an emoji sequence (ZWJ
+/// family, flag, skin tone) never takes more than two columns.
+fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.chars().all(char::is_control) {
+        return 0;
+    }
+    grapheme.width().min(2)
+}
+
+/// Terminal columns taken by `text`.
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Cuts `text` to at most `max_width` columns, ending with "…" when something was cut.
+/// Grapheme clusters are never split and control characters (newlines, tabs) become spaces.
+pub fn truncate(text: &str, max_width: usize) -> String {
+    let text = text.replace(char::is_control, " ");
+    if display_width(&text) <= max_width {
+        return text;
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme_width(grapheme);
+        // keep one column for the ellipsis
+        if width + grapheme_width + 1 > max_width {
+            break;
+        }
+        truncated.push_str(grapheme);
+        width += grapheme_width;
+    }
+    if max_width > 0 {
+        truncated.push_str(ELLIPSIS);
+    }
+    truncated
+}
+
+/// Truncates `text` then pads it with spaces to exactly `width` columns, for table columns.
+pub fn pad(text: &str, width: usize) -> String {
+    let mut padded = truncate(text, width);
+    let padding = width.saturating_sub(display_width(&padded));
+    padded.extend(std::iter::repeat_n(' ', padding));
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_of_mixed_scripts() {
+        assert_eq!(display_width("GitHub"), 6);
+        assert_eq!(display_width("知乎 - 有问题"), 13);
+        assert_eq!(display_width("네이버"), 6);
+        assert_eq!(display_width("カタカナ ひらがな"), 17);
+        assert_eq!(display_width("ＡＢＣ！"), 8);
+        assert_eq!(display_width("مرحبا بالعالم"), 13);
+        assert_eq!(display_width("שלום"), 4);
+    }
+
+    #[test]
+    fn width_of_combining_marks_and_emoji() {
+        // e + combining acute accent
+        assert_eq!(display_width("Cafe\u{301}"), 4);
+        assert_eq!(display_width("🚀 Launch"), 9);
+        // family joined with ZWJ, a flag and a skin tone modifier are one glyph each
+        assert_eq!(display_width("👨\u{200d}👩\u{200d}👧"), 2);
+        assert_eq!(display_width("🇫🇷"), 2);
+        assert_eq!(display_width("👍🏽"), 2);
+    }
+
+    #[test]
+    fn truncate_keeps_graphemes_whole() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("YouTube - Broadcast Yourself", 10), "YouTube -…");
+        // a wide character that does not fit is dropped rather than split
+        assert_eq!(truncate("知乎知乎知乎", 6), "知乎…");
+        assert_eq!(display_width(&truncate("知乎知乎知乎", 6)), 5);
+        assert_eq!(truncate("Cafe\u{301} Cafe\u{301}", 5), "Cafe\u{301}…");
+        assert_eq!(truncate("👨\u{200d}👩\u{200d}👧 family", 3), "👨\u{200d}👩\u{200d}👧…");
+        assert_eq!(truncate("anything", 0), "");
+    }
+
+    #[test]
+    fn pad_fills_exact_width() {
+        let titles = [
+            "Inbox (3) - mail",
+            "知乎 - 有问题，就会有答案",
+            "네이버 뉴스",
+            "ニュース｜ＮＨＫ",
+            "🎵 Playlist 👨\u{200d}👩\u{200d}👧",
+            "Re\u{301}sume\u{301} Builder",
+            "ويكيبيديا، الموسوعة الحرة",
+            "line\nbreak\ttab",
+        ];
+        for title in titles {
+            for width in [5, 12, 30] {
+                let padded = pad(title, width);
+                assert_eq!(display_width(&padded), width, "{:?} padded to {}", title, width);
+                assert!(!padded.contains(char::is_control));
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of (timestamp second, rss KB) samples covering a fixed time window.
+pub struct TimeSeries {
+    pub window: u64,
+    pub samples: VecDeque<(u64, i32)>,
+    pub ewma: Option<f64>,
+    ewma_alpha: f64,
+}
+
+/// Statistics of the samples currently held by a `TimeSeries`.
+pub struct SeriesStats {
+    pub latest: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub slope: f64, // KB per second, least squares fit
+    pub r_squared: f64, // how well the linear fit explains the samples, 1.0 is a straight line
+    pub ewma: f64,
+}
+
+impl TimeSeries {
+    pub fn new(window: u64, ewma_alpha: f64) -> Self {
+        Self { window, samples: VecDeque::new(), ewma: None, ewma_alpha }
+    }
+
+    /// Appends a sample and drops the ones that fell out of the window. One sample at or
+    /// before the start of the window is kept, so the samples always span the full window
+    /// even when the sampling period does not divide it.
+    pub fn push(&mut self, timestamp: u64, rss: i32) {
+        self.samples.push_back((timestamp, rss));
+        while self.samples.len() >= 2 && timestamp.saturating_sub(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+        self.ewma = Some(match self.ewma {
+            Some(ewma) => self.ewma_alpha * rss as f64 + (1.0 - self.ewma_alpha) * ewma,
+            None => rss as f64,
+        });
+    }
+
+    /// The window is full once the samples span the whole configured duration.
+    pub fn is_full(&self) -> bool {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(oldest, _)), Some(&(latest, _))) => latest - oldest >= self.window,
+            _ => false,
+        }
+    }
+
+    pub fn stats(&self) -> Option<SeriesStats> {
+        let &(_, latest) = self.samples.back()?;
+        let n = self.samples.len() as f64;
+        let mean = self.samples.iter().map(|&(_, rss)| rss as f64).sum::<f64>() / n;
+        let variance = self.samples.iter().map(|&(_, rss)| (rss as f64 - mean).powi(2)).sum::<f64>() / n;
+
+        let t_mean = self.samples.iter().map(|&(t, _)| t as f64).sum::<f64>() / n;
+        let (mut covariance, mut t_variance) = (0.0, 0.0);
+        for &(t, rss) in self.samples.iter() {
+            covariance += (t as f64 - t_mean) * (rss as f64 - mean);
+            t_variance += (t as f64 - t_mean).powi(2);
+        }
+        let slope = if t_variance > 0.0 { covariance / t_variance } else { 0.0 };
+        let r_squared = if t_variance > 0.0 && variance > 0.0 {
+            covariance.powi(2) / (t_variance * variance * n)
+        } else {
+            0.0
+        };
+
+        Some(SeriesStats {
+            latest: latest as f64,
+            mean,
+            stddev: variance.sqrt(),
+            slope,
+            r_squared,
+            ewma: self.ewma.unwrap_or(mean),
+        })
+    }
+}
+
+impl SeriesStats {
+    /// Relative memory change over the window according to `metric`, 0.05 means 5%.
+    /// - mean: distance of the latest sample from the window mean
+    /// - stddev: coefficient of variation of the window
+    /// - slope: growth over the whole window predicted by the linear fit
+    /// - ewma: distance of the latest sample from the exponentially weighted mean
+    pub fn change_rate(&self, metric: &str, window: u64) -> Option<f64> {
+        if self.mean <= 0.0 {
+            return None;
+        }
+        match metric {
+            "mean" => Some((self.latest - self.mean).abs() / self.mean),
+            "stddev" => Some(self.stddev / self.mean),
+            "slope" => Some((self.slope * window as f64).abs() / self.mean),
+            "ewma" if self.ewma > 0.0 => Some((self.latest - self.ewma).abs() / self.ewma),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes one sample every `tick` seconds from 0 up to `until`, rss grows by `step` per sample.
+    fn series(window: u64, tick: u64, until: u64, step: i32) -> TimeSeries {
+        let mut series = TimeSeries::new(window, 0.5);
+        for (i, timestamp) in (0..=until).step_by(tick as usize).enumerate() {
+            series.push(timestamp, 1000 + step * i as i32);
+        }
+        series
+    }
+
+    #[test]
+    fn push_keeps_one_sample_before_the_window() {
+        // 7s ticks over a 30s window: 0 7 14 21 28 35 42
+        let series = series(30, 7, 42, 0);
+        let timestamps: Vec<u64> = series.samples.iter().map(|&(t, _)| t).collect();
+        assert_eq!(timestamps, vec![7, 14, 21, 28, 35, 42]);
+    }
+
+    #[test]
+    fn is_full_with_a_non_dividing_tick() {
+        assert!(!series(30, 7, 28, 0).is_full());
+        assert!(series(30, 7, 35, 0).is_full());
+        // stays full as the window slides
+        for until in (35..200).step_by(7) {
+            assert!(series(30, 7, until, 0).is_full(), "not full at {}", until);
+        }
+    }
+
+    #[test]
+    fn is_full_with_a_dividing_tick() {
+        assert!(!series(30, 10, 20, 0).is_full());
+        assert!(series(30, 10, 30, 0).is_full());
+        assert_eq!(series(30, 10, 60, 0).samples.len(), 4);
+    }
+
+    #[test]
+    fn stats_of_a_linear_series() {
+        // 7 KB every 7s is 1 KB/s
+        let series = series(30, 7, 42, 7);
+        let stats = series.stats().unwrap();
+        assert_eq!(stats.latest, 1042.0);
+        assert!((stats.mean - 1024.5).abs() < 1e-9);
+        assert!((stats.slope - 1.0).abs() < 1e-9);
+        assert!((stats.change_rate("slope", 30).unwrap() - 30.0 / 1024.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stats_of_a_flat_series() {
+        let stats = series(30, 7, 42, 0).stats().unwrap();
+        assert_eq!(stats.stddev, 0.0);
+        assert_eq!(stats.slope, 0.0);
+        for metric in ["mean", "stddev", "slope", "ewma"] {
+            assert_eq!(stats.change_rate(metric, 30), Some(0.0));
+        }
+        assert_eq!(stats.change_rate("median", 30), None);
+    }
+
+    #[test]
+    fn stats_of_an_empty_series() {
+        assert!(TimeSeries::new(30, 0.5).stats().is_none());
+    }
+}
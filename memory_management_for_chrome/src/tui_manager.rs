@@ -0,0 +1,361 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::control_manager;
+use crate::memory_manager::STRATEGIES;
+use crate::text_width;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const SPARKLINE_WIDTH: usize = 20;
+const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One tab of the `snapshot` control method.
+#[derive(Deserialize)]
+struct TabRow {
+    tab_id: i32,
+    title: String,
+    pid: i32,
+    rss: i32, // KB
+    is_active: bool,
+    pinned: bool,
+    tier: String,
+    protected: bool,
+    idle_time: Option<u64>,
+    change_rate: Option<f64>,
+    history: Vec<i32>,
+    upcoming_victim: bool,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    strategy: String,
+    total_rss: i32,
+    browser_rss: i32,
+    rss_high_watermark: i32,
+    rss_low_watermark: i32,
+    status: Value,
+    tabs: Vec<TabRow>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortKey {
+    Rss,
+    IdleTime,
+    ChangeRate,
+}
+
+impl SortKey {
+    fn next(self) -> SortKey {
+        match self {
+            SortKey::Rss => SortKey::IdleTime,
+            SortKey::IdleTime => SortKey::ChangeRate,
+            SortKey::ChangeRate => SortKey::Rss,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Rss => "rss",
+            SortKey::IdleTime => "idle time",
+            SortKey::ChangeRate => "change rate",
+        }
+    }
+}
+
+/// Top-like view of a running manager. Everything shown comes from the `snapshot` control method,
+/// so the table is the one the daemon decides on, and every key is a control request.
+pub struct TuiManager {
+    socket_path: PathBuf,
+    snapshot: Option<Snapshot>,
+    sort_key: SortKey,
+    selected_tab: Option<i32>,
+    message: String,
+}
+
+impl TuiManager {
+    pub fn new(socket_path: &Path) -> Self {
+        Self {
+            socket_path: socket_path.to_path_buf(),
+            snapshot: None,
+            sort_key: SortKey::Rss,
+            selected_tab: None,
+            message: String::new(),
+        }
+    }
+
+    /// Takes over the terminal until `q` is pressed.
+    pub fn run(&mut self) -> io::Result<()> {
+        // fail before touching the terminal when no manager is running
+        self.refresh()?;
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        let mut last_refresh = Instant::now();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = REFRESH_INTERVAL.saturating_sub(last_refresh.elapsed());
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Up => self.move_selection(-1),
+                        KeyCode::Down => self.move_selection(1),
+                        KeyCode::Char('s') => self.sort_key = self.sort_key.next(),
+                        KeyCode::Char('x') => self.act_on_selected("kill"),
+                        KeyCode::Char('d') => self.act_on_selected("discard"),
+                        KeyCode::Char('f') => self.act_on_selected("freeze"),
+                        KeyCode::Char('p') => self.toggle_protection(),
+                        KeyCode::Char('t') => self.next_strategy(),
+                        KeyCode::Char(' ') => self.toggle_pause(),
+                        _ => continue,
+                    }
+                }
+            }
+            if last_refresh.elapsed() >= REFRESH_INTERVAL {
+                if let Err(err) = self.refresh() {
+                    self.message = format!("Cannot reach the manager: {}", err);
+                }
+                last_refresh = Instant::now();
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> io::Result<()> {
+        let result = control_manager::call(&self.socket_path, "snapshot", json!({}))?
+            .map_err(|err| io::Error::other(err.message))?;
+        let snapshot: Snapshot = serde_json::from_value(result).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.snapshot = Some(snapshot);
+        if self.selected_tab.is_none() {
+            self.selected_tab = self.sorted_tabs().first().map(|tab| tab.tab_id);
+        }
+        Ok(())
+    }
+
+    /// Sends a control request, the outcome is shown in the footer.
+    fn request(&mut self, method: &str, params: Value) {
+        self.message = match control_manager::call(&self.socket_path, method, params) {
+            Ok(Ok(_)) => format!("{}: done", method),
+            Ok(Err(err)) => format!("{}: {}", method, err.message),
+            Err(err) => format!("{}: {}", method, err),
+        };
+        let _ = self.refresh();
+    }
+
+    fn sorted_tabs(&self) -> Vec<&TabRow> {
+        let Some(snapshot) = self.snapshot.as_ref() else {
+            return Vec::new();
+        };
+        let mut tabs: Vec<&TabRow> = snapshot.tabs.iter().collect();
+        match self.sort_key {
+            SortKey::Rss => tabs.sort_by_key(|tab| std::cmp::Reverse(tab.rss)),
+            SortKey::IdleTime => tabs.sort_by_key(|tab| std::cmp::Reverse(tab.idle_time)),
+            // the slowest changing tabs are the next victims of memory_change_rate, unknown rates last
+            SortKey::ChangeRate => tabs.sort_by(|a, b| {
+                let rate = |tab: &TabRow| tab.change_rate.unwrap_or(f64::INFINITY);
+                rate(a).total_cmp(&rate(b))
+            }),
+        }
+        tabs
+    }
+
+    fn selected_index(&self, tabs: &[&TabRow]) -> Option<usize> {
+        let selected_tab = self.selected_tab?;
+        tabs.iter().position(|tab| tab.tab_id == selected_tab)
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let tabs = self.sorted_tabs();
+        if tabs.is_empty() {
+            return;
+        }
+        let index = self.selected_index(&tabs).map_or(0, |index| {
+            index.saturating_add_signed(offset).min(tabs.len() - 1)
+        });
+        self.selected_tab = Some(tabs[index].tab_id);
+    }
+
+    fn act_on_selected(&mut self, method: &str) {
+        if let Some(tab_id) = self.selected_tab {
+            self.request(method, json!({ "tab_id": tab_id }));
+        }
+    }
+
+    fn toggle_protection(&mut self) {
+        let tabs = self.sorted_tabs();
+        let Some(tab) = self.selected_index(&tabs).map(|index| tabs[index]) else {
+            return;
+        };
+        let method = if tab.protected { "unprotect" } else { "protect" };
+        let tab_id = tab.tab_id;
+        self.request(method, json!({ "tab_id": tab_id }));
+    }
+
+    fn next_strategy(&mut self) {
+        let Some(snapshot) = self.snapshot.as_ref() else {
+            return;
+        };
+        let index = STRATEGIES.iter().position(|strategy| *strategy == snapshot.strategy).map_or(0, |index| index + 1);
+        let strategy = STRATEGIES[index % STRATEGIES.len()];
+        self.request("set_strategy", json!({ "strategy": strategy }));
+    }
+
+    fn toggle_pause(&mut self) {
+        let paused = self
+            .snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.status["paused_by_user"].as_bool())
+            .unwrap_or(false);
+        self.request(if paused { "resume" } else { "pause" }, json!({}));
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header_area, gauge_area, table_area, footer_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let tabs = self.sorted_tabs();
+        if let Some(snapshot) = self.snapshot.as_ref() {
+            let status = &snapshot.status;
+            let mut state = Vec::new();
+            if status["paused_by_user"].as_bool() == Some(true) {
+                state.push("paused");
+            }
+            if status["reclaim_paused"].as_bool() == Some(true) {
+                state.push("paused after failed ticks");
+            }
+            if status["guardrail"]["breaker_open"].as_bool() == Some(true) {
+                state.push("circuit breaker open");
+            }
+            let victims: Vec<String> = tabs
+                .iter()
+                .filter(|tab| tab.upcoming_victim)
+                .map(|tab| tab.tab_id.to_string())
+                .collect();
+            let header = format!(
+                "Strategy: {}  {}\nBrowser: {} MB  Tabs: {} MB  High watermark: {} MB  Low watermark: {} MB\nUpcoming victims: {}",
+                snapshot.strategy,
+                if state.is_empty() { "reclaiming".to_string() } else { state.join(", ") },
+                snapshot.browser_rss / 1024,
+                snapshot.total_rss / 1024,
+                snapshot.rss_high_watermark / 1024,
+                snapshot.rss_low_watermark / 1024,
+                if victims.is_empty() { "none".to_string() } else { victims.join(", ") },
+            );
+            frame.render_widget(Paragraph::new(header), header_area);
+
+            let ratio = snapshot.total_rss as f64 / snapshot.rss_high_watermark.max(1) as f64;
+            let color = if ratio >= 1.0 { Color::Red } else if ratio >= 0.8 { Color::Yellow } else { Color::Green };
+            let gauge = Gauge::default()
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio.min(1.0))
+                .label(format!("{:.0}% of high watermark", ratio * 100.0));
+            frame.render_widget(gauge, gauge_area);
+        }
+
+        let header = Row::new(["ID", "PID", "RSS MB", "Idle", "Rate", "Tier", "Flags", "Memory", "Title"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(8),
+            Constraint::Length(5),
+            Constraint::Length(SPARKLINE_WIDTH as u16),
+            Constraint::Fill(1),
+        ];
+        // what is left for the title once the fixed columns and the one column gaps are drawn
+        let fixed_width: u16 = widths
+            .iter()
+            .map(|width| match width {
+                Constraint::Length(length) => length + 1,
+                _ => 0,
+            })
+            .sum();
+        let title_width = table_area.width.saturating_sub(fixed_width) as usize;
+        let rows = tabs.iter().map(|tab| {
+            let mut flags = String::new();
+            flags.push(if tab.is_active { 'A' } else { ' ' });
+            flags.push(if tab.pinned { 'P' } else { ' ' });
+            flags.push(if tab.protected { 'S' } else { ' ' });
+            flags.push(if tab.upcoming_victim { '!' } else { ' ' });
+            let style = if tab.upcoming_victim {
+                Style::default().fg(Color::Red)
+            } else if tab.protected {
+                Style::default().fg(Color::Cyan)
+            } else if tab.is_active {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default()
+            };
+            Row::new([
+                Cell::from(tab.tab_id.to_string()),
+                Cell::from(tab.pid.to_string()),
+                Cell::from(format!("{:.1}", tab.rss as f64 / 1024.0)),
+                Cell::from(tab.idle_time.map(format_duration).unwrap_or_default()),
+                Cell::from(tab.change_rate.map(|rate| format!("{:.3}", rate)).unwrap_or_default()),
+                Cell::from(tab.tier.clone()),
+                Cell::from(flags),
+                Cell::from(sparkline(&tab.history, SPARKLINE_WIDTH)),
+                Cell::from(text_width::truncate(&tab.title, title_width)),
+            ])
+            .style(style)
+        });
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::TOP).title(format!(" Tabs by {} ", self.sort_key.as_str())))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut table_state = TableState::default().with_selected(self.selected_index(&tabs));
+        frame.render_stateful_widget(table, table_area, &mut table_state);
+
+        let footer = format!(
+            "↑/↓ select  x kill  d discard  f freeze  p protect  s sort  t strategy  space pause  q quit\n{}",
+            self.message
+        );
+        frame.render_widget(Paragraph::new(footer).style(Style::default().fg(Color::DarkGray)), footer_area);
+    }
+}
+
+/// Renders the last `width` samples with block characters scaled between their min and max.
+fn sparkline(values: &[i32], width: usize) -> String {
+    let values = &values[values.len().saturating_sub(width)..];
+    let (Some(min), Some(max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    let range = (max - min).max(1) as f64;
+    values
+        .iter()
+        .map(|value| {
+            let level = ((value - min) as f64 / range * (SPARKLINE_LEVELS.len() - 1) as f64).round() as usize;
+            SPARKLINE_LEVELS[level]
+        })
+        .collect()
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        _ => format!("{}h", seconds / 3600),
+    }
+}
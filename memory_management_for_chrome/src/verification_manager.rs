@@ -0,0 +1,329 @@
+use std::collections::{HashMap, VecDeque};
+use log::{info, warn};
+
+use crate::config_manager::VerificationConfig;
+use crate::escalation_manager::Tier;
+use crate::process_handle::{process_start_time, ProcessKey};
+use crate::tab_manager::TabProcessInfo;
+
+const DISCARD_ACK_TIMEOUT: u64 = 10; // second for the extension to confirm a discard
+
+/// Identifies a tab across renderers. A discarded tab reloads in a new renderer under a new
+/// tab_inner_id, only the Chrome tab id of the extension channel stays the same.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TabKey {
+    Chrome(i32),
+    Inner(i32),
+}
+
+impl TabKey {
+    pub fn of(tab: &TabProcessInfo) -> Self {
+        if tab.chrome_tab_id != -1 {
+            TabKey::Chrome(tab.chrome_tab_id)
+        } else {
+            TabKey::Inner(tab.tab_inner_id)
+        }
+    }
+
+    fn matches(&self, tab: &TabProcessInfo) -> bool {
+        *self == TabKey::of(tab)
+    }
+}
+
+/// A reclaim action followed on the next ticks.
+pub struct TrackedAction {
+    pub process: ProcessKey,
+    pub tab: TabKey,
+    pub strategy: String,
+    pub tier: Tier,
+    pub taken_at: u64,           // elapsed time
+    pub tab_rss_before: i32,     // KB
+    pub browser_rss_before: i32, // KB, every Chrome process, the same for every action of a tick
+}
+
+/// What became of the renderer once an action is judged.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Exited,
+    Respawned { rss: i32 }, // the tab got a new renderer
+    Survived { rss: i32 },  // same renderer, paged out, frozen or still alive after a kill
+}
+
+/// Effect of the actions of one strategy since start, memory in KB.
+#[derive(Default)]
+pub struct ReclaimStats {
+    pub actions: u64,
+    pub exited: u64,
+    pub respawned: u64,
+    pub survived: u64,
+    pub reclaimed: i64,     // tab rss before the action minus what the tab uses afterwards
+    pub browser_delta: i64, // share of the browser rss change since the tick the action was taken on
+}
+
+impl ReclaimStats {
+    fn status(&self) -> serde_json::Value {
+        let per_action = |kb: i64| if self.actions == 0 { 0 } else { kb * 1024 / self.actions as i64 };
+        serde_json::json!({
+            "actions": self.actions,
+            "exited": self.exited,
+            "respawned": self.respawned,
+            "survived": self.survived,
+            "reclaimed_bytes": self.reclaimed * 1024,
+            "bytes_per_action": per_action(self.reclaimed),
+            "browser_bytes_per_action": per_action(self.browser_delta),
+        })
+    }
+}
+
+/// Follows every action for `window` seconds to measure the memory it gave back.
+///
+/// A released tab that gets a new renderer right away is being reloaded, by the page or by an
+/// extension. After `respawn_limit` respawns within `respawn_window` the tab is in a reload loop
+/// and is left alone for `backoff` seconds, doubled on every new loop.
+pub struct VerificationManager {
+    pub config: VerificationConfig,
+    pub pending: Vec<TrackedAction>,
+    pub tick_actions: HashMap<u64, i64>, // taken_at -> actions taken on that tick, they split its browser rss change
+    pub strategy_stats: HashMap<String, ReclaimStats>,
+    pub tab_respawns: HashMap<TabKey, VecDeque<u64>>, // elapsed time of each respawn
+    pub tab_backoff: HashMap<TabKey, (u64, u32)>,     // (backed off until, loops seen)
+    pub discards: HashMap<i32, (ProcessKey, u64)>,    // Chrome tab id -> renderer and elapsed time of an unconfirmed discard
+}
+
+impl VerificationManager {
+    pub fn new(config: &VerificationConfig) -> Self {
+        Self {
+            config: config.clone(),
+            pending: Vec::new(),
+            tick_actions: HashMap::new(),
+            strategy_stats: HashMap::new(),
+            tab_respawns: HashMap::new(),
+            tab_backoff: HashMap::new(),
+            discards: HashMap::new(),
+        }
+    }
+
+    /// Remembers a discard sent to the extension until it answers.
+    pub fn expect_discard(&mut self, chrome_tab_id: i32, process: ProcessKey, elapsed_time: u64) {
+        self.discards.insert(chrome_tab_id, (process, elapsed_time));
+    }
+
+    /// Matches the answers of the extension with the discards sent. Returns the renderers of the
+    /// discards Chrome refused or left unanswered for DISCARD_ACK_TIMEOUT, with the reason.
+    pub fn confirm_discards(&mut self, mut acks: HashMap<i32, Result<(), String>>, elapsed_time: u64) -> Vec<(ProcessKey, String)> {
+        let mut failed = Vec::new();
+        self.discards.retain(|chrome_tab_id, &mut (process, sent_at)| match acks.remove(chrome_tab_id) {
+            Some(Ok(())) => false,
+            Some(Err(reason)) => {
+                failed.push((process, reason));
+                false
+            }
+            None if elapsed_time >= sent_at + DISCARD_ACK_TIMEOUT => {
+                failed.push((process, format!("no confirmation within {} seconds", DISCARD_ACK_TIMEOUT)));
+                false
+            }
+            None => true,
+        });
+        failed
+    }
+
+    pub fn track(&mut self, action: TrackedAction) {
+        *self.tick_actions.entry(action.taken_at).or_default() += 1;
+        self.pending.push(action);
+    }
+
+    pub fn is_backed_off(&self, tab: TabKey, elapsed_time: u64) -> bool {
+        self.tab_backoff.get(&tab).is_some_and(|&(until, _)| elapsed_time < until)
+    }
+
+    /// Judges the actions whose window is over, and right away the ones whose tab already respawned.
+    pub fn update(&mut self, tabs: &[TabProcessInfo], browser_rss: i32, elapsed_time: u64) {
+        let pending = std::mem::take(&mut self.pending);
+        for action in pending {
+            let tab = tabs.iter().find(|tab| action.tab.matches(tab));
+            let outcome = match tab {
+                Some(tab) if tab.tab_process_id != -1 && tab.process_key() != action.process => {
+                    Some(Outcome::Respawned { rss: tab.tab_rss })
+                }
+                _ if elapsed_time < action.taken_at + self.config.window => None,
+                Some(tab) if tab.process_key() == action.process => Some(Outcome::Survived { rss: tab.tab_rss }),
+                // the tab was closed or lost its renderer, unless the process still runs it is gone
+                _ if process_start_time(action.process.pid) == Some(action.process.start_time) => {
+                    Some(Outcome::Survived { rss: action.tab_rss_before })
+                }
+                _ => Some(Outcome::Exited),
+            };
+            match outcome {
+                Some(outcome) => self.judge(&action, outcome, browser_rss, elapsed_time),
+                None => self.pending.push(action),
+            }
+        }
+        let pending = &self.pending;
+        self.tick_actions.retain(|taken_at, _| pending.iter().any(|action| action.taken_at == *taken_at));
+    }
+
+    /// Drops the respawn history and back off of closed tabs.
+    pub fn retain_tabs(&mut self, keep: impl Fn(&TabKey) -> bool) {
+        self.tab_respawns.retain(|tab, _| keep(tab));
+        self.tab_backoff.retain(|tab, _| keep(tab));
+    }
+
+    pub fn status(&self, elapsed_time: u64) -> serde_json::Value {
+        let strategies: serde_json::Map<String, serde_json::Value> = self
+            .strategy_stats
+            .iter()
+            .map(|(strategy, stats)| (strategy.clone(), stats.status()))
+            .collect();
+        let mut backed_off: Vec<i32> = Vec::new();
+        let mut backed_off_chrome: Vec<i32> = Vec::new();
+        for tab in self.tab_backoff.keys().filter(|tab| self.is_backed_off(**tab, elapsed_time)) {
+            match *tab {
+                TabKey::Chrome(chrome_tab_id) => backed_off_chrome.push(chrome_tab_id),
+                TabKey::Inner(tab_inner_id) => backed_off.push(tab_inner_id),
+            }
+        }
+        serde_json::json!({ "strategies": strategies, "backed_off_tabs": backed_off, "backed_off_chrome_tabs": backed_off_chrome })
+    }
+
+    fn judge(&mut self, action: &TrackedAction, outcome: Outcome, browser_rss: i32, elapsed_time: u64) {
+        let reclaimed = match outcome {
+            Outcome::Exited => action.tab_rss_before,
+            Outcome::Respawned { rss } | Outcome::Survived { rss } => action.tab_rss_before - rss,
+        };
+        // every action of a tick sees the same browser rss change, each gets its share of it
+        let tick_actions = self.tick_actions.get(&action.taken_at).copied().unwrap_or(1).max(1);
+        let browser_delta = (action.browser_rss_before - browser_rss) as i64 / tick_actions;
+        let stats = self.strategy_stats.entry(action.strategy.clone()).or_default();
+        stats.actions += 1;
+        stats.reclaimed += reclaimed as i64;
+        stats.browser_delta += browser_delta;
+        match outcome {
+            Outcome::Exited => stats.exited += 1,
+            Outcome::Respawned { .. } => stats.respawned += 1,
+            Outcome::Survived { .. } => stats.survived += 1,
+        }
+        if action.tier.is_released() && matches!(outcome, Outcome::Survived { .. }) {
+            warn!("Process with PID {} survived tier {}", action.process.pid, action.tier);
+        }
+        info!(
+            "Tier {} on Tab ID: {} ({}) reclaimed {} KB, browser rss changed by {} KB over {} actions",
+            action.tier,
+            action.process.tab_inner_id,
+            action.strategy,
+            reclaimed,
+            browser_rss - action.browser_rss_before,
+            tick_actions
+        );
+
+        // only a released tab coming straight back is a reload loop, a paged out tab may just be reloaded by the user
+        if action.tier.is_released() && matches!(outcome, Outcome::Respawned { .. }) {
+            self.record_respawn(action.tab, action.process.tab_inner_id, elapsed_time);
+        }
+    }
+
+    fn record_respawn(&mut self, tab: TabKey, tab_inner_id: i32, elapsed_time: u64) {
+        let respawns = self.tab_respawns.entry(tab).or_default();
+        respawns.push_back(elapsed_time);
+        while respawns.front().is_some_and(|&time| time + self.config.respawn_window <= elapsed_time) {
+            respawns.pop_front();
+        }
+        if respawns.len() < self.config.respawn_limit {
+            return;
+        }
+        respawns.clear();
+        let loops = self.tab_backoff.get(&tab).map_or(0, |&(_, loops)| loops) + 1;
+        let backoff = self
+            .config
+            .backoff
+            .saturating_mul(1 << (loops - 1).min(16))
+            .min(self.config.max_backoff);
+        warn!(
+            "Tab ID: {} keeps respawning after being reclaimed, leaving it alone for {} seconds",
+            tab_inner_id, backoff
+        );
+        self.tab_backoff.insert(tab, (elapsed_time + backoff, loops));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(tab_inner_id: i32, taken_at: u64) -> TrackedAction {
+        TrackedAction {
+            // no such process, a closed tab is judged as exited
+            process: ProcessKey { tab_inner_id, pid: i32::MAX, start_time: 1 },
+            tab: TabKey::Inner(tab_inner_id),
+            strategy: "rss_limit".to_string(),
+            tier: Tier::Kill,
+            taken_at,
+            tab_rss_before: 1000,
+            browser_rss_before: 10_000,
+        }
+    }
+
+    #[test]
+    fn actions_of_one_tick_share_the_browser_delta() {
+        let mut manager = VerificationManager::new(&VerificationConfig::default());
+        for tab_inner_id in 1..=4 {
+            manager.track(action(tab_inner_id, 0));
+        }
+        manager.update(&[], 6_000, 30);
+        let stats = &manager.strategy_stats["rss_limit"];
+        assert_eq!(stats.actions, 4);
+        assert_eq!(stats.exited, 4);
+        assert_eq!(stats.reclaimed, 4000);
+        assert_eq!(stats.browser_delta, 4000);
+        assert!(manager.pending.is_empty() && manager.tick_actions.is_empty());
+    }
+
+    #[test]
+    fn actions_wait_for_the_window() {
+        let mut manager = VerificationManager::new(&VerificationConfig::default());
+        manager.track(action(1, 0));
+        manager.update(&[], 6_000, 10);
+        assert_eq!(manager.pending.len(), 1);
+        assert!(manager.strategy_stats.is_empty());
+    }
+
+    #[test]
+    fn respawn_loop_backs_off() {
+        let mut manager = VerificationManager::new(&VerificationConfig::default());
+        let respawned = TabProcessInfo { tab_inner_id: 1, chrome_tab_id: -1, tab_process_id: 42, ..Default::default() };
+        for time in [0, 60] {
+            manager.track(action(1, time));
+            manager.update(std::slice::from_ref(&respawned), 10_000, time + 3);
+        }
+        assert!(manager.is_backed_off(TabKey::Inner(1), 63));
+        assert!(!manager.is_backed_off(TabKey::Inner(1), 63 + 600));
+    }
+
+    #[test]
+    fn refused_and_unanswered_discards_are_returned() {
+        let mut manager = VerificationManager::new(&VerificationConfig::default());
+        let process = |tab_inner_id| ProcessKey { tab_inner_id, pid: 100 + tab_inner_id, start_time: 1 };
+        for chrome_tab_id in 1..=3 {
+            manager.expect_discard(chrome_tab_id, process(chrome_tab_id), 0);
+        }
+        let acks = HashMap::from([(1, Ok(())), (2, Err("active tab".to_string())), (9, Ok(()))]);
+        let failed = manager.confirm_discards(acks, 5);
+        assert!(failed == vec![(process(2), "active tab".to_string())]);
+        assert_eq!(manager.discards.len(), 1);
+        let failed = manager.confirm_discards(HashMap::new(), DISCARD_ACK_TIMEOUT);
+        assert!(failed.len() == 1 && failed[0].0 == process(3));
+        assert!(manager.discards.is_empty());
+    }
+
+    #[test]
+    fn a_discarded_tab_is_followed_by_its_chrome_tab_id() {
+        let mut manager = VerificationManager::new(&VerificationConfig::default());
+        for (time, tab_inner_id) in [(0, 1), (60, 2)] {
+            // the tab comes back in a new renderer, with a new tab_inner_id
+            let reloaded = TabProcessInfo { tab_inner_id: tab_inner_id + 1, chrome_tab_id: 7, tab_process_id: 42, ..Default::default() };
+            manager.track(TrackedAction { tab: TabKey::Chrome(7), tier: Tier::Discard, ..action(tab_inner_id, time) });
+            manager.update(std::slice::from_ref(&reloaded), 10_000, time + 3);
+        }
+        assert_eq!(manager.strategy_stats["rss_limit"].respawned, 2);
+        assert!(manager.is_backed_off(TabKey::Chrome(7), 63));
+    }
+}
@@ -1,5 +1,6 @@
 import json
 import os
+import socket
 from http.server import BaseHTTPRequestHandler, HTTPServer
 
 class SimpleRequestHandler(BaseHTTPRequestHandler):
@@ -41,7 +42,13 @@ class SimpleRequestHandler(BaseHTTPRequestHandler):
 
 def run(server_class=HTTPServer, handler_class=SimpleRequestHandler, port=8080):
     server_address = ('127.0.0.1', port)
-    httpd = server_class(server_address, handler_class)
+    listen_fd = os.environ.get("LISTEN_FD")
+    if listen_fd:
+        # socket activation: systemd already bound and listens on the port
+        httpd = server_class(server_address, handler_class, bind_and_activate=False)
+        httpd.socket = socket.socket(fileno=int(listen_fd))
+    else:
+        httpd = server_class(server_address, handler_class)
     print(f"Server running at http://127.0.0.1:{port}/")
     httpd.serve_forever()
 
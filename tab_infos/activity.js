@@ -0,0 +1,26 @@
+// Reports user input and media playback of the page to the background service worker.
+const INPUT_REPORT_INTERVAL = 5000; // ms between two interaction reports of a page
+let lastReport = 0;
+
+function reportInteraction() {
+  const now = Date.now();
+  if (now - lastReport < INPUT_REPORT_INTERVAL) {
+    return;
+  }
+  lastReport = now;
+  chrome.runtime.sendMessage({ type: 'interaction', time: now }).catch(() => {});
+}
+
+function reportMedia() {
+  const playing = Array.from(document.querySelectorAll('video, audio'))
+    .some(media => !media.paused && !media.ended);
+  chrome.runtime.sendMessage({ type: 'media', playing }).catch(() => {});
+}
+
+for (const event of ['keydown', 'pointerdown', 'wheel', 'touchstart']) {
+  window.addEventListener(event, reportInteraction, { capture: true, passive: true });
+}
+// media events do not bubble, they are caught on the way down
+for (const event of ['play', 'pause', 'ended']) {
+  document.addEventListener(event, reportMedia, true);
+}
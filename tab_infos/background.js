@@ -1,3 +1,17 @@
+const IDLE_DETECTION_INTERVAL = 60; // second without input before the system counts as idle
+const NATIVE_HOST_NAME = 'chrome_memory_manager';
+const CHANNEL_URL = 'ws://127.0.0.1:8080/channel';
+const CHANNEL_RETRY_DELAY = 5000; // ms before connecting again after the channel closed
+const RESYNC_INTERVAL = 60000;    // ms between two full snapshots over the channel
+const NOTIFICATION_ICON = 'icon.png';
+
+let idleState = 'active'; // active, idle or locked
+let idleSince = null;     // ms
+const lastInteraction = {}; // tab id -> ms of the last input in the page or activation of the tab
+const mediaFrames = {};     // tab id -> ids of the frames playing audio or video
+let channel = null;         // { post(event) } to the manager while a channel is open
+let lastResync = 0;         // ms
+
 async function sendTabInfoToServer(tabs) {
   let tabsJson = JSON.stringify(tabs, null, 4);
 
@@ -20,42 +34,235 @@ async function sendTabInfoToServer(tabs) {
   }
 }
 
-async function saveTabInfos(tabId, changeInfo, tab) {
-  if (changeInfo.status === "complete" && tab.title) {
-    let tabs = await chrome.tabs.query({});
+// Sends an event over the channel, false when it is not open.
+function sendEvent(event) {
+  if (!channel) {
+    return false;
+  }
+  channel.post(event);
+  return true;
+}
+
+async function openChannel(opened) {
+  channel = opened;
+  await sendSnapshot();
+}
+
+function onCommand(command) {
+  handleCommand(command).catch(error => console.error('Error in channel command:', error));
+}
+
+// Keeps a channel open to the manager. Tab changes are streamed over it as events and the
+// manager sends commands back. Chrome starts the manager as a native messaging host when the
+// host is installed; otherwise a WebSocket reaches a manager that is already running. While
+// neither is open the full tab list is POSTed instead.
+function connectChannel() {
+  let port = chrome.runtime.connectNative(NATIVE_HOST_NAME);
+  let opened = { post: event => port.postMessage(event) };
+  // a running host greets the extension first, a missing one only disconnects
+  port.onMessage.addListener(command => {
+    if (command.command === 'hello') {
+      openChannel(opened);
+    } else {
+      onCommand(command);
+    }
+  });
+  port.onDisconnect.addListener(() => {
+    console.log('Native messaging host disconnected:', chrome.runtime.lastError?.message);
+    if (channel === opened) {
+      channel = null;
+    }
+    connectWebSocket();
+  });
+}
 
-    await Promise.all(tabs.map(async tab => {
-      tab.pid = await chrome.processes.getProcessIdForTab(tab.id);
-      return tab;
-    }));
+function connectWebSocket() {
+  let socket = new WebSocket(CHANNEL_URL);
+  let opened = { post: event => socket.send(JSON.stringify(event)) };
+  socket.onopen = () => {
+    console.log('Channel to the manager open');
+    openChannel(opened);
+  };
+  socket.onmessage = message => onCommand(JSON.parse(message.data));
+  socket.onclose = () => {
+    if (channel === opened) {
+      channel = null;
+    }
+    setTimeout(connectChannel, CHANNEL_RETRY_DELAY);
+  };
+}
 
-    sendTabInfoToServer(tabs);
+async function handleCommand(command) {
+  switch (command.command) {
+    case 'discard':
+      // the manager terminates the renderer itself when the discard is refused or never answered
+      try {
+        let discarded = await chrome.tabs.discard(command.tabId);
+        sendEvent({ type: 'discarded', tabId: command.tabId, ok: discarded?.discarded === true });
+      } catch (error) {
+        sendEvent({ type: 'discarded', tabId: command.tabId, ok: false, error: error.message });
+      }
+      break;
+    case 'reload':
+      await chrome.tabs.reload(command.tabId);
+      break;
+    case 'protect':
+      // Chrome does not discard the tab on its own either while the manager protects it
+      await chrome.tabs.update(command.tabId, { autoDiscardable: !command.protected });
+      break;
+    case 'notify':
+      chrome.notifications.create({
+        type: 'basic',
+        iconUrl: NOTIFICATION_ICON,
+        title: command.title,
+        message: command.message,
+      });
+      break;
+    default:
+      console.error('Unknown channel command:', command);
   }
 }
 
-async function onActivatedHandler() {
-    let tabs = await chrome.tabs.query({});
-    await Promise.all(tabs.map(async tab => {
-      tab.pid = await chrome.processes.getProcessIdForTab(tab.id);
-      return tab;
-    }));
-    sendTabInfoToServer(tabs);
+// A tab with its renderer pid and activity.
+async function describeTab(tab) {
+  tab.pid = await chrome.processes.getProcessIdForTab(tab.id);
+  tab.lastInteraction = lastInteraction[tab.id] ?? null;
+  tab.mediaPlaying = (mediaFrames[tab.id]?.size ?? 0) > 0;
+  return tab;
 }
 
-async function handleTabRemoved(tabId) {
+// The windows with their state, the tab groups, the window the user looked at last and the
+// system idle state.
+async function collectSignals() {
+  let windows = await chrome.windows.getAll();
+  let groups = await chrome.tabGroups.query({});
+  let lastFocusedWindow = await chrome.windows.getLastFocused().catch(() => null);
+  return {
+    windows: windows.map(window => ({ id: window.id, state: window.state, focused: window.focused })),
+    groups: groups.map(group => ({ id: group.id, title: group.title ?? '', collapsed: group.collapsed })),
+    lastFocusedWindowId: lastFocusedWindow?.id ?? null,
+    idleState,
+    idleSince,
+  };
+}
+
+// The tab list along with the browser signals.
+async function collectTabInfos() {
   let tabs = await chrome.tabs.query({});
+  await Promise.all(tabs.map(describeTab));
+  return { ...await collectSignals(), tabs };
+}
 
-  await Promise.all(tabs.map(async tab => {
-    tab.pid = await chrome.processes.getProcessIdForTab(tab.id);
-    return tab;
-  }));
+async function sendSnapshot() {
+  lastResync = Date.now();
+  sendEvent({ type: 'snapshot', ...await collectTabInfos() });
+}
+
+async function sendSignals() {
+  sendEvent({ type: 'signals', ...await collectSignals() });
+}
+
+async function sendTabEvent(type, tab) {
+  if (channel) {
+    sendEvent({ type, tab: await describeTab(tab) });
+  }
+}
 
-  sendTabInfoToServer(tabs);
+async function saveTabInfos(tabId, changeInfo, tab) {
+  if (changeInfo.status === 'loading') {
+    // the frames of the previous page are gone without a pause message
+    delete mediaFrames[tabId];
+  }
+  if (channel) {
+    await sendTabEvent('updated', tab);
+  } else if (changeInfo.status === "complete" && tab.title) {
+    sendTabInfoToServer(await collectTabInfos());
+  }
+}
+
+async function onActivatedHandler(activeInfo) {
+    if (activeInfo) {
+      lastInteraction[activeInfo.tabId] = Date.now();
+    }
+    if (!channel) {
+      sendTabInfoToServer(await collectTabInfos());
+    } else if (activeInfo) {
+      sendEvent({ type: 'activated', tabId: activeInfo.tabId, windowId: activeInfo.windowId, time: Date.now() });
+    } else if (Date.now() - lastResync >= RESYNC_INTERVAL) {
+      await sendSnapshot();
+    } else {
+      // window states have no change event, and the messages keep the service worker alive
+      await sendSignals();
+    }
+}
+
+async function handleTabRemoved(tabId) {
+  delete lastInteraction[tabId];
+  delete mediaFrames[tabId];
+  if (!sendEvent({ type: 'removed', tabId })) {
+    sendTabInfoToServer(await collectTabInfos());
+  }
+}
+
+function handleIdleStateChanged(state) {
+  idleState = state;
+  if (state === 'active') {
+    idleSince = null;
+  } else if (idleSince === null) {
+    // the system is reported idle once it has been without input for the detection interval
+    idleSince = Date.now() - (state === 'idle' ? IDLE_DETECTION_INTERVAL * 1000 : 0);
+  }
+  if (channel) {
+    sendSignals();
+  }
+}
+
+function handleActivityMessage(message, sender) {
+  let tabId = sender.tab?.id;
+  if (tabId === undefined) {
+    return;
+  }
+  if (message.type === 'interaction') {
+    lastInteraction[tabId] = Math.max(lastInteraction[tabId] ?? 0, message.time);
+  } else if (message.type === 'media') {
+    let frames = mediaFrames[tabId] ??= new Set();
+    if (message.playing) {
+      frames.add(sender.frameId);
+    } else {
+      frames.delete(sender.frameId);
+    }
+  } else {
+    return;
+  }
+  sendEvent({
+    type: 'activity',
+    tabId,
+    lastInteraction: lastInteraction[tabId] ?? null,
+    mediaPlaying: (mediaFrames[tabId]?.size ?? 0) > 0,
+  });
+}
+
+function handleSignalsChanged() {
+  if (channel) {
+    sendSignals().catch(error => console.error('Error sending browser signals:', error));
+  }
 }
 
+chrome.idle.setDetectionInterval(IDLE_DETECTION_INTERVAL);
+chrome.idle.queryState(IDLE_DETECTION_INTERVAL).then(handleIdleStateChanged);
+chrome.idle.onStateChanged.addListener(handleIdleStateChanged);
+chrome.runtime.onMessage.addListener(handleActivityMessage);
+chrome.tabs.onCreated.addListener(tab => sendTabEvent('created', tab));
 chrome.tabs.onUpdated.addListener(saveTabInfos);
 chrome.tabs.onActivated.addListener(onActivatedHandler);
 chrome.tabs.onRemoved.addListener(handleTabRemoved);
+chrome.windows.onFocusChanged.addListener(handleSignalsChanged);
+chrome.windows.onCreated.addListener(handleSignalsChanged);
+chrome.windows.onRemoved.addListener(handleSignalsChanged);
+chrome.tabGroups.onCreated.addListener(handleSignalsChanged);
+chrome.tabGroups.onUpdated.addListener(handleSignalsChanged);
+chrome.tabGroups.onRemoved.addListener(handleSignalsChanged);
+connectChannel();
 setInterval(() => {
   onActivatedHandler().catch(error => console.error('Error in periodic onActivatedHandler:', error));
-}, 3000);
\ No newline at end of file
+}, 3000);